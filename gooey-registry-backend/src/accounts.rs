@@ -0,0 +1,324 @@
+//! Registry-managed user accounts and API tokens, used by the `accounts`
+//! authentication mode. This lets a registry own its scopes without relying on
+//! GitHub for identity.
+//!
+//! Tokens are only ever stored as hashes. The plaintext token is returned once,
+//! when it's created.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+const TOKEN_PREFIX: &str = "gooey_";
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+/// Something a token can be allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenAction {
+    Publish,
+    ManageOwners,
+    Deprecate,
+    ManageTokens,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: u64,
+    pub name: String,
+}
+
+/// A token as it's stored, without the token itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub user: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// The scopes this token can act on. Empty means every scope the user
+    /// owns.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+
+    pub actions: Vec<TokenAction>,
+
+    /// When the token was created, in seconds since the Unix epoch.
+    pub created_at: u64,
+
+    /// When the token stops working, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl TokenInfo {
+    pub fn allows(&self, action: TokenAction, scope: &str) -> bool {
+        self.actions.contains(&action)
+            && (self.scopes.is_empty() || self.scopes.iter().any(|allowed| allowed == scope))
+    }
+
+    /// Check that a token created with this one can't do anything this one
+    /// couldn't.
+    pub fn check_can_create(&self, new: &NewToken) -> anyhow::Result<()> {
+        if !self.actions.contains(&TokenAction::ManageTokens) {
+            bail!("this token is not allowed to manage tokens");
+        }
+
+        if let Some(user) = &new.user {
+            if user != &self.user {
+                bail!("tokens can only be created for their own user");
+            }
+        }
+
+        if let Some(action) = new
+            .actions
+            .iter()
+            .find(|action| !self.actions.contains(action))
+        {
+            bail!("this token is not allowed to grant {:?}", action);
+        }
+
+        if !self.scopes.is_empty() {
+            if new.scopes.is_empty() {
+                bail!("this token is limited to scopes, so new tokens must be too");
+            }
+
+            if let Some(scope) = new.scopes.iter().find(|scope| !self.scopes.contains(scope)) {
+                bail!("this token is not allowed to act on scope {}", scope);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+}
+
+/// The options for a new token.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NewToken {
+    /// The user to create the token for. Only needed when using the admin key.
+    pub user: Option<String>,
+
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub scopes: Vec<String>,
+
+    pub actions: Vec<TokenAction>,
+
+    pub expires_in_days: Option<u64>,
+}
+
+/// Who is authenticated by a token: the token itself and the user it belongs
+/// to.
+#[derive(Debug, Clone)]
+pub struct AccountAccess {
+    pub user: User,
+    pub token: TokenInfo,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct AccountsFile {
+    #[serde(default)]
+    users: BTreeMap<String, User>,
+
+    /// Tokens keyed by the hash of the token.
+    #[serde(default)]
+    tokens: BTreeMap<String, TokenInfo>,
+}
+
+pub struct AccountStore {
+    path: PathBuf,
+    contents: Mutex<AccountsFile>,
+}
+
+impl AccountStore {
+    /// Open the account store at the given path, or start an empty one if it
+    /// doesn't exist yet.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let contents = match fs_err::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("malformed account store at {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => AccountsFile::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            contents: Mutex::new(contents),
+        })
+    }
+
+    pub fn create_user(&self, name: &str) -> anyhow::Result<User> {
+        let mut contents = self.contents.lock().unwrap();
+
+        if contents.users.contains_key(name) {
+            bail!("user {} already exists", name);
+        }
+
+        // Ids are never reused, so that a removed owner's id can't be taken
+        // over by someone else.
+        let id = contents
+            .users
+            .values()
+            .map(|user| user.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let user = User {
+            id,
+            name: name.to_owned(),
+        };
+
+        contents.users.insert(name.to_owned(), user.clone());
+        self.save(&contents)?;
+
+        Ok(user)
+    }
+
+    pub fn find_user(&self, name: &str) -> Option<User> {
+        self.contents.lock().unwrap().users.get(name).cloned()
+    }
+
+    pub fn find_user_by_id(&self, id: u64) -> Option<User> {
+        let contents = self.contents.lock().unwrap();
+        contents.users.values().find(|user| user.id == id).cloned()
+    }
+
+    /// Create a token, returning the plaintext token along with what was
+    /// stored about it. The token never outlives `max_expires_at`, if given.
+    pub fn create_token(
+        &self,
+        user: &str,
+        new: NewToken,
+        max_expires_at: Option<u64>,
+    ) -> anyhow::Result<(String, TokenInfo)> {
+        let mut contents = self.contents.lock().unwrap();
+
+        if !contents.users.contains_key(user) {
+            bail!("user {} does not exist", user);
+        }
+
+        if new.actions.is_empty() {
+            bail!("a token must allow at least one action");
+        }
+
+        let now = now();
+        let expires_at = new
+            .expires_in_days
+            .map(|days| now.saturating_add(days.saturating_mul(SECONDS_PER_DAY)));
+        let expires_at = match (expires_at, max_expires_at) {
+            (Some(expires_at), Some(max)) => Some(expires_at.min(max)),
+            (expires_at, max) => expires_at.or(max),
+        };
+
+        let token = format!("{}{}", TOKEN_PREFIX, random_hex(32));
+        let info = TokenInfo {
+            id: random_hex(8),
+            user: user.to_owned(),
+            description: new.description,
+            scopes: new.scopes,
+            actions: new.actions,
+            created_at: now,
+            expires_at,
+        };
+
+        contents.tokens.insert(hash_token(&token), info.clone());
+        self.save(&contents)?;
+
+        Ok((token, info))
+    }
+
+    /// List the tokens belonging to a user.
+    pub fn tokens(&self, user: &str) -> Vec<TokenInfo> {
+        let contents = self.contents.lock().unwrap();
+        let mut tokens: Vec<TokenInfo> = contents
+            .tokens
+            .values()
+            .filter(|token| token.user == user)
+            .cloned()
+            .collect();
+
+        tokens.sort_by_key(|token| token.created_at);
+        tokens
+    }
+
+    /// Revoke a token by its id. Returns the revoked token, if there was one
+    /// and it belonged to `user`.
+    pub fn revoke_token(&self, user: Option<&str>, id: &str) -> anyhow::Result<Option<TokenInfo>> {
+        let mut contents = self.contents.lock().unwrap();
+
+        let hash = contents
+            .tokens
+            .iter()
+            .find(|(_, token)| token.id == id && user.is_none_or(|user| token.user == user))
+            .map(|(hash, _)| hash.clone());
+
+        let revoked = match hash {
+            Some(hash) => contents.tokens.remove(&hash),
+            None => return Ok(None),
+        };
+
+        self.save(&contents)?;
+        Ok(revoked)
+    }
+
+    /// Find out who a token belongs to. Unknown and expired tokens give
+    /// `None`.
+    pub fn authenticate(&self, token: &str) -> Option<AccountAccess> {
+        let contents = self.contents.lock().unwrap();
+        let info = contents.tokens.get(&hash_token(token))?;
+
+        if info.is_expired(now()) {
+            return None;
+        }
+
+        let user = contents.users.get(&info.user)?;
+
+        Some(AccountAccess {
+            user: user.clone(),
+            token: info.clone(),
+        })
+    }
+
+    fn save(&self, contents: &AccountsFile) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so that a crash can't leave the
+        // store half written.
+        let temp_path = self.path.with_extension("json.tmp");
+        fs_err::write(&temp_path, serde_json::to_string_pretty(contents)?)?;
+        fs_err::rename(&temp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    let data: Vec<u8> = (0..bytes).map(|_| rng.gen()).collect();
+    hex::encode(data)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
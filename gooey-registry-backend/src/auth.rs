@@ -1,4 +1,5 @@
 use std::fmt;
+use std::path::PathBuf;
 
 use anyhow::format_err;
 use constant_time_eq::constant_time_eq;
@@ -11,6 +12,7 @@ use rocket::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::accounts::{AccountAccess, AccountStore, TokenAction};
 use crate::error::Error;
 use crate::{config::Config, error::ApiErrorStatus};
 
@@ -18,8 +20,18 @@ use crate::{config::Config, error::ApiErrorStatus};
 #[serde(tag = "type", content = "value", rename_all = "kebab-case")]
 pub enum AuthMode {
     ApiKey(String),
-    DoubleApiKey { read: Option<String>, write: String },
+    DoubleApiKey {
+        read: Option<String>,
+        write: String,
+    },
     GithubOAuth,
+    /// Users and their tokens are managed by the registry itself and stored
+    /// at `path`. The admin key can create users and manage every token.
+    #[serde(rename_all = "kebab-case")]
+    Accounts {
+        path: Option<PathBuf>,
+        admin_key: String,
+    },
     Unauthenticated,
 }
 
@@ -45,15 +57,23 @@ impl fmt::Debug for AuthMode {
             AuthMode::ApiKey(_) => write!(formatter, "API key"),
             AuthMode::DoubleApiKey { .. } => write!(formatter, "double API key"),
             AuthMode::GithubOAuth => write!(formatter, "Github OAuth"),
+            AuthMode::Accounts { .. } => write!(formatter, "registry accounts"),
             AuthMode::Unauthenticated => write!(formatter, "no authentication"),
         }
     }
 }
 
+fn bearer_token(request: &Request<'_>) -> Option<String> {
+    match request.headers().get_one("authorization") {
+        Some(key) if key.starts_with("Bearer ") => Some((key[6..].trim()).to_owned()),
+        _ => None,
+    }
+}
+
 fn match_api_key<T>(request: &Request<'_>, key: &str, result: T) -> Outcome<T, Error> {
-    let input_api_key: String = match request.headers().get_one("authorization") {
-        Some(key) if key.starts_with("Bearer ") => (key[6..].trim()).to_owned(),
-        _ => {
+    let input_api_key = match bearer_token(request) {
+        Some(key) => key,
+        None => {
             return format_err!("API key required")
                 .status(Status::Unauthorized)
                 .into();
@@ -103,6 +123,78 @@ async fn verify_github_token(request: &Request<'_>) -> Outcome<WriteAccess, Erro
     }
 }
 
+/// Look up a GitHub user by their login, like `octocat`. Scope owners are
+/// stored by id, but people know each other by login.
+pub async fn github_user_by_login(login: &str) -> anyhow::Result<GithubInfo> {
+    fetch_github_user(format!("https://api.github.com/users/{}", login)).await
+}
+
+pub async fn github_user_by_id(id: u64) -> anyhow::Result<GithubInfo> {
+    fetch_github_user(format!("https://api.github.com/user/{}", id)).await
+}
+
+async fn fetch_github_user(url: String) -> anyhow::Result<GithubInfo> {
+    let client = Client::new();
+    let response = client
+        .get(&url)
+        .header("accept", "application/json")
+        .header("user-agent", "gooey")
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(format_err!(
+            "could not find GitHub user at {} ({})",
+            url,
+            response.status()
+        ));
+    }
+
+    Ok(response.json::<GithubInfo>().await?)
+}
+
+async fn verify_account_token(
+    request: &Request<'_>,
+    admin_key: &str,
+) -> Outcome<WriteAccess, Error> {
+    let token = match bearer_token(request) {
+        Some(token) => token,
+        None => {
+            return format_err!("API token required")
+                .status(Status::Unauthorized)
+                .into();
+        }
+    };
+
+    if constant_time_eq(admin_key.as_bytes(), token.as_bytes()) {
+        return Outcome::Success(WriteAccess::ApiKey);
+    }
+
+    let accounts = match request
+        .guard::<&State<Option<AccountStore>>>()
+        .await
+        .succeeded()
+    {
+        Some(accounts) => accounts,
+        None => {
+            return format_err!("registry accounts are not configured")
+                .status(Status::InternalServerError)
+                .into();
+        }
+    };
+
+    match accounts
+        .inner()
+        .as_ref()
+        .and_then(|accounts| accounts.authenticate(&token))
+    {
+        Some(access) => Outcome::Success(WriteAccess::Account(access)),
+        None => format_err!("Invalid or expired API token")
+            .status(Status::Unauthorized)
+            .into(),
+    }
+}
+
 pub enum ReadAccess {
     Public,
     ApiKey,
@@ -121,6 +213,7 @@ impl<'r> FromRequest<'r> for ReadAccess {
         match &config.auth {
             AuthMode::Unauthenticated => Outcome::Success(ReadAccess::Public),
             AuthMode::GithubOAuth => Outcome::Success(ReadAccess::Public),
+            AuthMode::Accounts { .. } => Outcome::Success(ReadAccess::Public),
             AuthMode::ApiKey(key) => match_api_key(request, key, ReadAccess::ApiKey),
             AuthMode::DoubleApiKey { read, .. } => match read {
                 None => Outcome::Success(ReadAccess::Public),
@@ -133,6 +226,7 @@ impl<'r> FromRequest<'r> for ReadAccess {
 pub enum WriteAccess {
     ApiKey,
     Github(GithubInfo),
+    Account(AccountAccess),
 }
 
 impl WriteAccess {
@@ -141,10 +235,28 @@ impl WriteAccess {
         package_id: &PackageId,
         index: &PackageIndex,
     ) -> anyhow::Result<bool> {
-        let scope = package_id.name().scope();
+        self.can_write_scope(package_id.name().scope(), TokenAction::Publish, index)
+    }
 
+    pub fn can_write_scope(
+        &self,
+        scope: &str,
+        action: TokenAction,
+        index: &PackageIndex,
+    ) -> anyhow::Result<bool> {
         let has_permission = match self {
             WriteAccess::ApiKey => true,
+            WriteAccess::Account(access) => {
+                if !access.token.allows(action, scope) {
+                    false
+                } else if index.is_scope_owner(scope, &access.user.id)? {
+                    true
+                } else {
+                    // Like GitHub users, accounts can claim the scope matching
+                    // their name if nobody owns it yet
+                    access.user.name == scope && index.get_scope_owners(scope)?.is_empty()
+                }
+            }
             WriteAccess::Github(github_info) => {
                 match index.is_scope_owner(scope, github_info.id())? {
                     true => true,
@@ -159,6 +271,19 @@ impl WriteAccess {
 
         Ok(has_permission)
     }
+
+    /// Whether this user may change who owns a scope. Only the existing owners
+    /// of a scope can, along with holders of the registry's API key.
+    pub fn can_manage_scope(&self, scope: &str, index: &PackageIndex) -> anyhow::Result<bool> {
+        match self {
+            WriteAccess::ApiKey => Ok(true),
+            WriteAccess::Github(github_info) => index.is_scope_owner(scope, github_info.id()),
+            WriteAccess::Account(access) => {
+                Ok(access.token.allows(TokenAction::ManageOwners, scope)
+                    && index.is_scope_owner(scope, &access.user.id)?)
+            }
+        }
+    }
 }
 
 #[rocket::async_trait]
@@ -180,6 +305,7 @@ impl<'r> FromRequest<'r> for WriteAccess {
                 match_api_key(request, write, WriteAccess::ApiKey)
             }
             AuthMode::GithubOAuth => verify_github_token(request).await,
+            AuthMode::Accounts { admin_key, .. } => verify_account_token(request, admin_key).await,
         }
     }
 }
@@ -1,13 +1,20 @@
+use std::path::PathBuf;
+
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::{auth::AuthMode, storage::StorageMode};
+use crate::{auth::AuthMode, index_storage::IndexMode, storage::StorageMode};
 
 #[derive(Deserialize, Serialize)]
 pub struct Config {
     /// The URL of the Git repository containing the registry's package index.
-    pub index_url: Url,
+    /// Only needed when the index is stored in Git, which is the default.
+    pub index_url: Option<Url>,
+
+    /// Where the registry keeps its package index.
+    #[serde(default)]
+    pub index: IndexMode,
 
     /// The token that should be used by the registry to communicate with
     /// GitHub. If not specified, will try to use the machine's Git credential
@@ -17,6 +24,27 @@ pub struct Config {
     /// What kind of authentication is required to access endpoints.
     pub auth: AuthMode,
 
+    /// The name `/v1/whoami` gives for the write API key. Defaults to
+    /// "API key".
+    #[serde(default)]
+    pub api_key_label: Option<String>,
+
+    /// Where to keep the search index, so that it doesn't have to be rebuilt
+    /// every time the registry starts. Kept in memory if not set.
+    #[serde(default)]
+    pub search_index_path: Option<PathBuf>,
+
+    /// How often to rebuild the search index from scratch, in seconds. Search
+    /// is kept up to date as packages change, so this only catches changes made
+    /// to the index some other way. Defaults to an hour.
+    #[serde(default)]
+    pub search_rebuild_interval: Option<u64>,
+
+    /// Where to keep how many times each package has been downloaded, which
+    /// search results can be sorted by. Kept in memory if not set.
+    #[serde(default)]
+    pub downloads_path: Option<PathBuf>,
+
     /// Which storage backend to use.
     pub storage: StorageMode,
 
@@ -0,0 +1,115 @@
+//! Counts how many times each package has been downloaded, so that search
+//! results can be sorted by popularity.
+//!
+//! Counts are kept in memory and written out every so often, rather than on
+//! every download. A crash can lose the last few seconds of downloads, which
+//! is fine for something that's only used to order search results.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use libgooey::package_name::PackageName;
+
+/// How long to wait between writing the counts out.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct DownloadCounts {
+    /// Where the counts are kept. They're only kept in memory if not set.
+    path: Option<PathBuf>,
+    state: Mutex<CountsState>,
+}
+
+struct CountsState {
+    /// Downloads of every version of a package, keyed by `scope/name`.
+    counts: BTreeMap<String, u64>,
+    last_saved: Instant,
+    dirty: bool,
+}
+
+impl DownloadCounts {
+    pub fn open(path: Option<&Path>) -> anyhow::Result<Self> {
+        let counts = match path {
+            Some(path) => match fs_err::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .with_context(|| format!("malformed download counts at {}", path.display()))?,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(err) => return Err(err.into()),
+            },
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            path: path.map(Path::to_owned),
+            state: Mutex::new(CountsState {
+                counts,
+                last_saved: Instant::now(),
+                dirty: false,
+            }),
+        })
+    }
+
+    pub fn record(&self, name: &PackageName) {
+        let mut state = self.state.lock().unwrap();
+        *state.counts.entry(name.to_string()).or_insert(0) += 1;
+        state.dirty = true;
+
+        if state.last_saved.elapsed() >= SAVE_INTERVAL {
+            self.save(&mut state);
+        }
+    }
+
+    /// The number of downloads of the package with the given `scope/name`.
+    pub fn get(&self, id: &str) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.counts.get(id).copied().unwrap_or(0)
+    }
+
+    /// A copy of every count, keyed by `scope/name`.
+    pub fn all(&self) -> BTreeMap<String, u64> {
+        let state = self.state.lock().unwrap();
+        state.counts.clone()
+    }
+
+    fn save(&self, state: &mut CountsState) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        // Counting downloads shouldn't ever get in the way of them, so
+        // failures are only logged and tried again on the next download.
+        if let Err(err) = write_counts(path, &state.counts) {
+            println!("Failed to save download counts: {:?}", err);
+            return;
+        }
+
+        state.last_saved = Instant::now();
+        state.dirty = false;
+    }
+}
+
+impl Drop for DownloadCounts {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        if state.dirty {
+            self.save(&mut state);
+        }
+    }
+}
+
+fn write_counts(path: &Path, counts: &BTreeMap<String, u64>) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+
+    // Write to a temporary file first so that a crash can't leave the counts
+    // half written.
+    let temp_path = path.with_extension("json.tmp");
+    fs_err::write(&temp_path, serde_json::to_string(counts)?)?;
+    fs_err::rename(&temp_path, path)?;
+
+    Ok(())
+}
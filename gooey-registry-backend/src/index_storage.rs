@@ -0,0 +1,159 @@
+//! Decides where the registry keeps its package index. By default it's a Git
+//! repository that every change is pushed to, but a registry can also own its
+//! index in a local directory, so that it can run without a Git host.
+
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use git2::{Oid, Repository};
+use libgooey::{
+    git_util::{self, GitCredentials},
+    package_index::{PackageIndex, PackageIndexConfig},
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The name of the remote that a local index is mirrored to.
+const MIRROR_REMOTE: &str = "mirror";
+
+const DEFAULT_MIRROR_INTERVAL: u64 = 60;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum IndexMode {
+    /// Clone the repository at `index_url` and push every change to it.
+    #[default]
+    Git,
+
+    /// Keep the index in a local directory. Clients read it through
+    /// `/v1/index/`, as a sparse registry.
+    #[serde(rename_all = "kebab-case")]
+    Local {
+        path: PathBuf,
+
+        /// The registry's API URL, used to create the index's `config.json`
+        /// if it doesn't have one yet.
+        api: Option<Url>,
+
+        /// A Git repository to copy the index to in the background. The
+        /// registry owns the index, so the mirror is overwritten rather than
+        /// merged with.
+        mirror_url: Option<Url>,
+
+        /// How many seconds to wait between mirror pushes. Defaults to 60.
+        mirror_interval: Option<u64>,
+    },
+}
+
+pub fn open_index(
+    mode: &IndexMode,
+    index_url: Option<&Url>,
+    credentials: GitCredentials,
+) -> anyhow::Result<PackageIndex> {
+    match mode {
+        IndexMode::Git => {
+            let index_url =
+                index_url.context("index_url must be set when the index is stored in Git")?;
+
+            println!("Cloning package index repository...");
+            PackageIndex::new_temp(index_url, credentials)
+        }
+        IndexMode::Local {
+            path,
+            api,
+            mirror_url,
+            mirror_interval,
+        } => {
+            println!("Opening local package index at {}...", path.display());
+            let index = PackageIndex::new_local(path)?;
+
+            if !index.path().join("config.json").exists() {
+                let api = api.clone().with_context(|| {
+                    format!(
+                        "the index at {} has no config.json, set `api` so one can be created",
+                        path.display()
+                    )
+                })?;
+
+                index.set_config(&PackageIndexConfig {
+                    api,
+                    github_oauth_id: None,
+                    fallback_registries: Vec::new(),
+                })?;
+            }
+
+            if let Some(mirror_url) = mirror_url {
+                let interval = mirror_interval.unwrap_or(DEFAULT_MIRROR_INTERVAL);
+                spawn_mirror(
+                    index.path(),
+                    mirror_url,
+                    credentials,
+                    Duration::from_secs(interval),
+                )?;
+            }
+
+            Ok(index)
+        }
+    }
+}
+
+/// Start pushing the index at `index_path` to `mirror_url` whenever it
+/// changes, checking every `interval`.
+fn spawn_mirror(
+    index_path: &Path,
+    mirror_url: &Url,
+    credentials: GitCredentials,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let repository = Repository::open(index_path)?;
+
+    if repository.find_remote(MIRROR_REMOTE).is_ok() {
+        repository.remote_set_url(MIRROR_REMOTE, mirror_url.as_str())?;
+    } else {
+        repository.remote(MIRROR_REMOTE, mirror_url.as_str())?;
+    }
+
+    println!("Mirroring package index to {}", mirror_url);
+    let mirror_url = mirror_url.clone();
+
+    thread::spawn(move || {
+        let mut mirrored = None;
+
+        loop {
+            match mirror(&repository, &credentials, mirrored) {
+                Ok(head) => mirrored = head,
+                Err(err) => println!(
+                    "Failed to mirror package index to {}: {:?}",
+                    mirror_url, err
+                ),
+            }
+
+            thread::sleep(interval);
+        }
+    });
+
+    Ok(())
+}
+
+/// Push the index to the mirror if it has changed since `mirrored`, returning
+/// the commit the mirror is at now.
+fn mirror(
+    repository: &Repository,
+    credentials: &GitCredentials,
+    mirrored: Option<Oid>,
+) -> anyhow::Result<Option<Oid>> {
+    let head = repository.head()?.target();
+
+    if head != mirrored {
+        git_util::push(
+            repository,
+            credentials.clone(),
+            MIRROR_REMOTE,
+            "+refs/heads/main:refs/heads/main",
+        )?;
+    }
+
+    Ok(head)
+}
@@ -1,10 +1,14 @@
 #[macro_use]
 extern crate rocket;
 
+mod accounts;
 mod auth;
 mod config;
+mod downloads;
 mod error;
+mod index_storage;
 mod search;
+mod sparse_index;
 mod storage;
 
 #[cfg(test)]
@@ -12,7 +16,9 @@ mod tests;
 
 use std::convert::TryInto;
 use std::io::{Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
 use std::sync::RwLock;
+use std::time::Duration;
 
 use anyhow::{format_err, Context};
 use figment::{
@@ -20,10 +26,11 @@ use figment::{
     Figment,
 };
 use libgooey::{
+    git_util::GitCredentials,
     manifest::{Manifest, MANIFEST_FILE_NAME},
     package_id::PackageId,
-    package_index::PackageIndex,
-    package_name::PackageName,
+    package_index::{Deprecation, PackageIndex},
+    package_name::{validate_scope, PackageName},
 };
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::Header;
@@ -43,10 +50,14 @@ use serde_json::json;
 use storage::StorageMode;
 use zip::ZipArchive;
 
-use crate::auth::{ReadAccess, WriteAccess};
+use crate::accounts::{AccountStore, NewToken, TokenAction};
+use crate::auth::{github_user_by_id, github_user_by_login, AuthMode, ReadAccess, WriteAccess};
 use crate::config::Config;
+use crate::downloads::DownloadCounts;
 use crate::error::{ApiErrorContext, ApiErrorStatus, Error};
-use crate::search::SearchBackend;
+use crate::index_storage::open_index;
+use crate::search::{SearchBackend, SearchOptions};
+use crate::sparse_index::{IfNoneMatch, IndexFile};
 use crate::storage::{GcsStorage, LocalStorage, StorageBackend, StorageOutput};
 
 #[cfg(feature = "s3-storage")]
@@ -62,6 +73,7 @@ fn root() -> content::RawJson<serde_json::Value> {
 #[get("/v1/package-contents/<scope>/<name>/<version>")]
 async fn package_contents(
     storage: &State<Box<dyn StorageBackend>>,
+    downloads: &State<DownloadCounts>,
     _read: Result<ReadAccess, Error>,
     scope: String,
     name: String,
@@ -81,7 +93,10 @@ async fn package_contents(
     let package_id = PackageId::new(package_name, version);
 
     match storage.read(&package_id).await.map(ReaderStream::one) {
-        Ok(stream) => Ok((ContentType::GZIP, stream)),
+        Ok(stream) => {
+            downloads.record(package_id.name());
+            Ok((ContentType::GZIP, stream))
+        }
         Err(e) => Err(e).status(Status::NotFound),
     }
 }
@@ -104,16 +119,47 @@ async fn package_info(
     Ok(Json(serde_json::to_value(metadata)?))
 }
 
-#[get("/v1/package-search?<query>")]
+#[get("/v1/index/<path..>")]
+async fn index_file(
+    index: &State<PackageIndex>,
+    _read: Result<ReadAccess, Error>,
+    if_none_match: IfNoneMatch<'_>,
+    path: PathBuf,
+) -> Result<IndexFile, Error> {
+    // The config is readable by anyone, since it's how clients find out where
+    // to log in.
+    if path != Path::new("config.json") {
+        _read?;
+    }
+
+    IndexFile::read(index.path(), &path, if_none_match)
+}
+
+#[get("/v1/package-search?<query>&<limit>&<offset>&<sort>")]
 async fn package_search(
     search_backend: &State<RwLock<SearchBackend>>,
+    downloads: &State<DownloadCounts>,
     _read: Result<ReadAccess, Error>,
     query: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    sort: Option<String>,
 ) -> Result<Json<serde_json::Value>, Error> {
     _read?;
 
+    let mut options = SearchOptions::default();
+    if let Some(limit) = limit {
+        options.limit = limit;
+    }
+    if let Some(offset) = offset {
+        options.offset = offset;
+    }
+    if let Some(sort) = sort {
+        options.sort = sort.parse().status(Status::BadRequest)?;
+    }
+
     if let Ok(search_backend) = search_backend.read() {
-        let result = search_backend.search(&query)?;
+        let result = search_backend.search(&query, &options, downloads)?;
         Ok(Json(serde_json::to_value(result)?))
     } else {
         Err(
@@ -164,12 +210,17 @@ async fn publish(
     }
 
     // If a user can write but isn't in the scope owner file then we should add them!
-    if let WriteAccess::Github(github_info) = authorization {
-        let user_id = github_info.id();
+    let user_id = match &authorization {
+        WriteAccess::Github(github_info) => Some(*github_info.id()),
+        WriteAccess::Account(access) => Some(access.user.id),
+        WriteAccess::ApiKey => None,
+    };
+
+    if let Some(user_id) = user_id {
         let scope = package_id.name().scope();
 
-        if !index.is_scope_owner(scope, user_id)? {
-            index.add_scope_owner(scope, user_id)?;
+        if !index.is_scope_owner(scope, &user_id)? {
+            index.add_scope_owner(scope, &user_id)?;
         }
     }
 
@@ -192,17 +243,480 @@ async fn publish(
         .publish(&manifest)
         .context("could not publish package to index")?;
 
-    if let Ok(mut search_backend) = search_backend.try_write() {
-        // TODO: Recrawling the whole index for each publish is very wasteful!
-        // Eventually this will get too expensive and we should only add the new package.
-        search_backend.crawl_packages(&index)?;
-    }
+    update_search(search_backend, index, manifest.package_id().name()).await?;
 
     Ok(Json(json!({
         "message": "Package published successfully!"
     })))
 }
 
+#[get("/v1/scopes/<scope>/owners")]
+async fn scope_owners(
+    config: &State<Config>,
+    index: &State<PackageIndex>,
+    accounts: &State<Option<AccountStore>>,
+    _read: Result<ReadAccess, Error>,
+    scope: String,
+) -> Result<Json<serde_json::Value>, Error> {
+    _read?;
+
+    validate_scope(&scope).status(Status::BadRequest)?;
+    index.update()?;
+
+    let mut owners = Vec::new();
+    for id in index.get_scope_owners(&scope)? {
+        // Logins are only for display, so a failed lookup shouldn't fail the
+        // whole request. Owners are only GitHub users with GitHub OAuth.
+        let login = match (accounts.inner(), &config.auth) {
+            (Some(accounts), _) => accounts.find_user_by_id(id).map(|user| user.name),
+            (None, AuthMode::GithubOAuth) => github_user_by_id(id)
+                .await
+                .ok()
+                .map(|user| user.login().to_owned()),
+            (None, _) => None,
+        };
+
+        owners.push(json!({ "id": id, "login": login }));
+    }
+
+    Ok(Json(json!({
+        "scope": scope,
+        "owners": owners,
+    })))
+}
+
+#[put("/v1/scopes/<scope>/owners/<owner>")]
+async fn add_scope_owner(
+    config: &State<Config>,
+    index: &State<PackageIndex>,
+    accounts: &State<Option<AccountStore>>,
+    authorization: Result<WriteAccess, Error>,
+    _cli_version: Result<gooeyVersion, Error>,
+    scope: String,
+    owner: String,
+) -> Result<Json<serde_json::Value>, Error> {
+    _cli_version?;
+    let authorization = authorization?;
+
+    validate_scope(&scope).status(Status::BadRequest)?;
+    index.update()?;
+    check_can_manage_scope(&authorization, &scope, index)?;
+
+    let (owner_id, owner_name) = resolve_owner(&config.auth, accounts, &owner).await?;
+
+    if index.is_scope_owner(&scope, &owner_id)? {
+        return Err(
+            format_err!("{} already owns scope {}", owner_name, scope).status(Status::Conflict)
+        );
+    }
+
+    index
+        .add_scope_owner(&scope, &owner_id)
+        .context("could not add scope owner to index")?;
+
+    Ok(Json(json!({
+        "message": format!("Added {} as an owner of {}", owner_name, scope)
+    })))
+}
+
+#[delete("/v1/scopes/<scope>/owners/<owner>")]
+async fn remove_scope_owner(
+    config: &State<Config>,
+    index: &State<PackageIndex>,
+    accounts: &State<Option<AccountStore>>,
+    authorization: Result<WriteAccess, Error>,
+    _cli_version: Result<gooeyVersion, Error>,
+    scope: String,
+    owner: String,
+) -> Result<Json<serde_json::Value>, Error> {
+    _cli_version?;
+    let authorization = authorization?;
+
+    validate_scope(&scope).status(Status::BadRequest)?;
+    index.update()?;
+    check_can_manage_scope(&authorization, &scope, index)?;
+
+    let (owner_id, owner_name) = resolve_owner(&config.auth, accounts, &owner).await?;
+    let owners = index.get_scope_owners(&scope)?;
+
+    if !owners.contains(&owner_id) {
+        return Err(
+            format_err!("{} does not own scope {}", owner_name, scope).status(Status::NotFound)
+        );
+    }
+
+    // Without any owners, the scope could be claimed by whoever has a
+    // matching GitHub login.
+    if owners.len() == 1 {
+        return Err(
+            format_err!("cannot remove the last owner of scope {}", scope)
+                .status(Status::BadRequest),
+        );
+    }
+
+    index
+        .remove_scope_owner(&scope, &owner_id)
+        .context("could not remove scope owner from index")?;
+
+    Ok(Json(json!({
+        "message": format!("Removed {} as an owner of {}", owner_name, scope)
+    })))
+}
+
+#[put("/v1/package-deprecation/<scope>/<name>", data = "<deprecation>")]
+async fn deprecate(
+    index: &State<PackageIndex>,
+    search_backend: &State<RwLock<SearchBackend>>,
+    authorization: Result<WriteAccess, Error>,
+    _cli_version: Result<gooeyVersion, Error>,
+    scope: String,
+    name: String,
+    deprecation: Json<Deprecation>,
+) -> Result<Json<serde_json::Value>, Error> {
+    _cli_version?;
+    let authorization = authorization?;
+    let deprecation = deprecation.into_inner();
+
+    let package_name = find_package_to_deprecate(&authorization, index, scope, name)?;
+
+    if let Some(replacement) = &deprecation.replacement {
+        if replacement == &package_name {
+            return Err(
+                format_err!("a package cannot be replaced by itself").status(Status::BadRequest)
+            );
+        }
+
+        index
+            .get_package_metadata(replacement)
+            .with_context(|| format!("replacement package {} does not exist", replacement))
+            .status(Status::BadRequest)?;
+    }
+
+    index
+        .set_deprecation(&package_name, Some(deprecation))
+        .context("could not deprecate package in index")?;
+
+    update_search(search_backend, index, &package_name).await?;
+
+    Ok(Json(json!({
+        "message": format!("Deprecated {}", package_name)
+    })))
+}
+
+#[delete("/v1/package-deprecation/<scope>/<name>")]
+async fn undeprecate(
+    index: &State<PackageIndex>,
+    search_backend: &State<RwLock<SearchBackend>>,
+    authorization: Result<WriteAccess, Error>,
+    _cli_version: Result<gooeyVersion, Error>,
+    scope: String,
+    name: String,
+) -> Result<Json<serde_json::Value>, Error> {
+    _cli_version?;
+    let authorization = authorization?;
+
+    let package_name = find_package_to_deprecate(&authorization, index, scope, name)?;
+
+    if index
+        .get_package_metadata(&package_name)?
+        .deprecation
+        .is_none()
+    {
+        return Err(
+            format_err!("package {} is not deprecated", package_name).status(Status::NotFound)
+        );
+    }
+
+    index
+        .set_deprecation(&package_name, None)
+        .context("could not remove deprecation from index")?;
+
+    update_search(search_backend, index, &package_name).await?;
+
+    Ok(Json(json!({
+        "message": format!("Removed deprecation of {}", package_name)
+    })))
+}
+
+/// Check that a package exists and that the user may change its deprecation.
+fn find_package_to_deprecate(
+    authorization: &WriteAccess,
+    index: &PackageIndex,
+    scope: String,
+    name: String,
+) -> Result<PackageName, Error> {
+    let package_name = PackageName::new(scope, name)
+        .context("error parsing package name")
+        .status(Status::BadRequest)?;
+
+    index.update()?;
+    index.clear_cached_package(&package_name);
+
+    if !authorization.can_write_scope(package_name.scope(), TokenAction::Deprecate, index)? {
+        return Err(format_err!(
+            "you do not have permission to write in scope {}",
+            package_name.scope()
+        )
+        .status(Status::Unauthorized));
+    }
+
+    index
+        .get_package_metadata(&package_name)
+        .status(Status::NotFound)?;
+
+    Ok(package_name)
+}
+
+/// Update a package's search document after it changes in the index. The
+/// search backend is only locked while the update is started, so searches can
+/// go on while it runs.
+async fn update_search(
+    search_backend: &RwLock<SearchBackend>,
+    index: &PackageIndex,
+    name: &PackageName,
+) -> Result<(), Error> {
+    let update = search_backend
+        .read()
+        .ok()
+        .map(|search_backend| search_backend.update_package(index, name));
+
+    if let Some(update) = update {
+        update.await??;
+    }
+
+    Ok(())
+}
+
+/// Owners can be given by GitHub login or by numeric user id. Ids are used as
+/// they are, without checking that the user exists.
+///
+/// With registry accounts, owners are given by account name instead, and must
+/// exist. Logins only mean something with GitHub OAuth, so other registries
+/// only take ids.
+async fn resolve_owner(
+    auth: &AuthMode,
+    accounts: &Option<AccountStore>,
+    owner: &str,
+) -> Result<(u64, String), Error> {
+    if let Some(accounts) = accounts {
+        let user = match owner.parse::<u64>() {
+            Ok(id) => accounts.find_user_by_id(id),
+            Err(_) => accounts.find_user(owner),
+        };
+
+        return match user {
+            Some(user) => Ok((user.id, user.name)),
+            None => Err(format_err!("user {} does not exist", owner).status(Status::NotFound)),
+        };
+    }
+
+    if let Ok(id) = owner.parse::<u64>() {
+        return Ok((id, id.to_string()));
+    }
+
+    if !matches!(auth, AuthMode::GithubOAuth) {
+        return Err(
+            format_err!("owners are given by user id on this registry, not by login")
+                .status(Status::BadRequest),
+        );
+    }
+
+    let user = github_user_by_login(owner).await.status(Status::NotFound)?;
+
+    Ok((*user.id(), user.login().to_owned()))
+}
+
+fn check_can_manage_scope(
+    authorization: &WriteAccess,
+    scope: &str,
+    index: &PackageIndex,
+) -> Result<(), Error> {
+    if !authorization.can_manage_scope(scope, index)? {
+        return Err(
+            format_err!("only owners of scope {} can change its owners", scope)
+                .status(Status::Unauthorized),
+        );
+    }
+
+    Ok(())
+}
+
+#[get("/v1/whoami")]
+async fn whoami(
+    config: &State<Config>,
+    authorization: Result<WriteAccess, Error>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let identity = match authorization? {
+        WriteAccess::ApiKey => json!({
+            "kind": "api-key",
+            "name": config.api_key_label.as_deref().unwrap_or("API key"),
+        }),
+        WriteAccess::Github(github_info) => json!({
+            "kind": "github",
+            "name": github_info.login(),
+            "id": github_info.id(),
+        }),
+        WriteAccess::Account(access) => json!({
+            "kind": "account",
+            "name": access.user.name,
+            "id": access.user.id,
+            "token": access.token,
+        }),
+    };
+
+    Ok(Json(identity))
+}
+
+#[derive(serde::Deserialize)]
+struct NewUser {
+    name: String,
+}
+
+#[post("/v1/users", data = "<user>")]
+async fn create_user(
+    accounts: &State<Option<AccountStore>>,
+    authorization: Result<WriteAccess, Error>,
+    user: Json<NewUser>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let authorization = authorization?;
+    let accounts = enabled_accounts(accounts)?;
+
+    if !matches!(authorization, WriteAccess::ApiKey) {
+        return Err(
+            format_err!("only the registry admin can create users").status(Status::Unauthorized)
+        );
+    }
+
+    // Users can claim the scope matching their name, so names follow the same
+    // rules as scopes.
+    validate_scope(&user.name)
+        .context("invalid user name")
+        .status(Status::BadRequest)?;
+
+    let user = accounts.create_user(&user.name).status(Status::Conflict)?;
+
+    Ok(Json(json!({
+        "id": user.id,
+        "name": user.name,
+    })))
+}
+
+#[post("/v1/tokens", data = "<token>")]
+async fn create_token(
+    accounts: &State<Option<AccountStore>>,
+    authorization: Result<WriteAccess, Error>,
+    token: Json<NewToken>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let authorization = authorization?;
+    let accounts = enabled_accounts(accounts)?;
+    let token = token.into_inner();
+
+    let (user, max_expires_at) = match &authorization {
+        WriteAccess::ApiKey => {
+            let user = token
+                .user
+                .clone()
+                .context("a user is required when creating tokens with the admin key")
+                .status(Status::BadRequest)?;
+
+            (user, None)
+        }
+        WriteAccess::Account(access) => {
+            access
+                .token
+                .check_can_create(&token)
+                .status(Status::Unauthorized)?;
+
+            (access.user.name.clone(), access.token.expires_at)
+        }
+        WriteAccess::Github(_) => return Err(github_tokens_error()),
+    };
+
+    let (secret, info) = accounts
+        .create_token(&user, token, max_expires_at)
+        .status(Status::BadRequest)?;
+
+    Ok(Json(json!({
+        "token": secret,
+        "info": info,
+    })))
+}
+
+#[get("/v1/tokens?<user>")]
+async fn list_tokens(
+    accounts: &State<Option<AccountStore>>,
+    authorization: Result<WriteAccess, Error>,
+    user: Option<String>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let authorization = authorization?;
+    let accounts = enabled_accounts(accounts)?;
+
+    let user = token_owner(&authorization, user)?;
+
+    Ok(Json(json!({
+        "tokens": accounts.tokens(&user),
+    })))
+}
+
+#[delete("/v1/tokens/<id>")]
+async fn revoke_token(
+    accounts: &State<Option<AccountStore>>,
+    authorization: Result<WriteAccess, Error>,
+    id: String,
+) -> Result<Json<serde_json::Value>, Error> {
+    let authorization = authorization?;
+    let accounts = enabled_accounts(accounts)?;
+
+    // The admin can revoke anybody's tokens, users only their own.
+    let user = match &authorization {
+        WriteAccess::ApiKey => None,
+        _ => Some(token_owner(&authorization, None)?),
+    };
+
+    match accounts.revoke_token(user.as_deref(), &id)? {
+        Some(_) => Ok(Json(json!({
+            "message": format!("Revoked token {}", id)
+        }))),
+        None => Err(format_err!("token {} does not exist", id).status(Status::NotFound)),
+    }
+}
+
+fn enabled_accounts(accounts: &State<Option<AccountStore>>) -> Result<&AccountStore, Error> {
+    accounts.inner().as_ref().ok_or_else(|| {
+        format_err!("this registry does not use registry accounts").status(Status::NotFound)
+    })
+}
+
+/// Decide whose tokens a request is about. The admin has to say which user,
+/// everybody else can only manage their own tokens.
+fn token_owner(authorization: &WriteAccess, user: Option<String>) -> Result<String, Error> {
+    match authorization {
+        WriteAccess::ApiKey => user
+            .context("a user is required when managing tokens with the admin key")
+            .status(Status::BadRequest),
+        WriteAccess::Account(access) => {
+            if !access.token.actions.contains(&TokenAction::ManageTokens) {
+                return Err(format_err!("this token is not allowed to manage tokens")
+                    .status(Status::Unauthorized));
+            }
+
+            match user {
+                Some(user) if user != access.user.name => {
+                    Err(format_err!("you can only manage your own tokens")
+                        .status(Status::Unauthorized))
+                }
+                _ => Ok(access.user.name.clone()),
+            }
+        }
+        WriteAccess::Github(_) => Err(github_tokens_error()),
+    }
+}
+
+/// GitHub users don't have registry accounts, so there are no tokens to manage.
+fn github_tokens_error() -> Error {
+    format_err!("GitHub users do not have registry tokens").status(Status::Forbidden)
+}
+
 fn get_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>) -> anyhow::Result<Manifest> {
     let mut manifest_file = archive
         .by_name(MANIFEST_FILE_NAME)
@@ -227,6 +741,15 @@ pub fn server(figment: Figment) -> rocket::Rocket<Build> {
     let config: Config = figment.extract().expect("could not read configuration");
 
     println!("Using authentication mode: {:?}", config.auth);
+    let accounts = match &config.auth {
+        AuthMode::Accounts { path, .. } => {
+            let path = path
+                .as_deref()
+                .unwrap_or_else(|| Path::new("accounts.json"));
+            Some(AccountStore::open(path).expect("could not open account store"))
+        }
+        _ => None,
+    };
 
     println!("Using storage backend: {:?}", config.storage);
     let storage_backend: Box<dyn StorageBackend> = match config.storage {
@@ -240,11 +763,24 @@ pub fn server(figment: Figment) -> rocket::Rocket<Build> {
         }
     };
 
-    println!("Cloning package index repository...");
-    let package_index = PackageIndex::new_temp(&config.index_url, config.github_token).unwrap();
+    println!("Using index storage: {:?}", config.index);
+    let package_index = open_index(
+        &config.index,
+        config.index_url.as_ref(),
+        GitCredentials::from_token(config.github_token),
+    )
+    .expect("could not open package index");
 
     println!("Initializing search backend...");
-    let search_backend = SearchBackend::new(&package_index).unwrap();
+    let search_backend =
+        SearchBackend::new(&package_index, config.search_index_path.as_deref()).unwrap();
+    search_backend.spawn_rebuilds(
+        package_index.path().clone(),
+        Duration::from_secs(config.search_rebuild_interval.unwrap_or(60 * 60)),
+    );
+
+    let downloads = DownloadCounts::open(config.downloads_path.as_deref())
+        .expect("could not open download counts");
 
     rocket::custom(figment)
         .mount(
@@ -254,12 +790,25 @@ pub fn server(figment: Figment) -> rocket::Rocket<Build> {
                 package_contents,
                 publish,
                 package_info,
+                index_file,
                 package_search,
+                scope_owners,
+                add_scope_owner,
+                remove_scope_owner,
+                deprecate,
+                undeprecate,
+                whoami,
+                create_user,
+                create_token,
+                list_tokens,
+                revoke_token,
                 cors_options,
             ],
         )
         .manage(storage_backend)
         .manage(package_index)
+        .manage(accounts)
+        .manage(downloads)
         .manage(RwLock::new(search_backend))
         .attach(AdHoc::config::<Config>())
         .attach(Cors)
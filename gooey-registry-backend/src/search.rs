@@ -1,27 +1,66 @@
-use std::time::Instant;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use libgooey::package_index::PackageIndex;
+use anyhow::{bail, Context};
+use git2::{Commit, Oid, Repository, Sort};
+use libgooey::package_index::{
+    parse_package_versions, read_deprecation, Deprecation, PackageIndex, PackageMetadata,
+};
 use libgooey::package_name::PackageName;
+use rocket::tokio::task::{self, JoinHandle};
 use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
+use tantivy::directory::MmapDirectory;
+use tantivy::fastfield::FastFieldReader;
+use tantivy::query::{AllQuery, BooleanQuery, BoostQuery, Occur, Query, QueryParser, TermQuery};
 
 use serde::{Deserialize, Serialize};
 use tantivy::tokenizer::{LowerCaser, NgramTokenizer, TextAnalyzer};
 use tantivy::{schema::*, IndexReader, ReloadPolicy};
-use tantivy::{Index, IndexWriter};
+use tantivy::{DocId, Index, IndexWriter, Score, Searcher, SegmentReader};
 use walkdir::{DirEntry, WalkDir};
 
-static DOC_LIMIT: usize = 100;
+use crate::downloads::DownloadCounts;
+
+/// The most results a single search can return.
+pub const DOC_LIMIT: usize = 100;
+
+/// Bumped whenever the schema changes, so that search indexes kept on disk
+/// with an older schema are rebuilt.
+const SCHEMA_VERSION: u32 = 3;
+
+/// The file next to an on-disk search index that says which commit of the
+/// package index it was built from.
+const WATERMARK_FILE: &str = "gooey-watermark.json";
+
+#[derive(PartialEq, Serialize, Deserialize)]
+struct Watermark {
+    schema: u32,
+    commit: String,
+}
 
 pub struct SearchBackend {
     schema: Schema,
-    writer: IndexWriter,
+
+    /// Shared with the thread that rebuilds the search index now and then.
+    writer: Arc<Mutex<IndexWriter>>,
+
     reader: IndexReader,
     query_parser: QueryParser,
+
+    /// Where the watermark is kept, when the search index is on disk.
+    watermark_path: Option<PathBuf>,
 }
 
 impl SearchBackend {
-    pub fn new(package_index: &PackageIndex) -> anyhow::Result<Self> {
+    /// Create the search backend, keeping the search index in `path` if given
+    /// and in memory otherwise. An index on disk is only crawled again if the
+    /// package index has changed since it was last built.
+    pub fn new(package_index: &PackageIndex, path: Option<&Path>) -> anyhow::Result<Self> {
         let mut schema_builder = Schema::builder();
 
         let text_options = TextOptions::default()
@@ -32,13 +71,41 @@ impl SearchBackend {
             )
             .set_stored();
 
+        // The package's full name, like `scope/name`, used to find its
+        // document again when the package changes.
+        schema_builder.add_text_field("id", STRING);
         schema_builder.add_text_field("scope", text_options.clone());
         schema_builder.add_text_field("name", text_options.clone());
         schema_builder.add_text_field("versions", TEXT | STORED);
-        schema_builder.add_text_field("description", text_options);
+        schema_builder.add_text_field("description", text_options.clone());
+        schema_builder.add_text_field("keywords", text_options.clone());
+        schema_builder.add_text_field("tags", text_options);
+        schema_builder.add_text_field("deprecation", STORED);
+        schema_builder.add_text_field("realm", STORED);
+        schema_builder.add_text_field("license", STORED);
+        schema_builder.add_text_field("authors", STORED);
+
+        // When the package was last published, in seconds since the Unix
+        // epoch. Times are kept from one crawl to the next, see
+        // `publish_times`.
+        schema_builder.add_u64_field("updated", STORED | FAST);
+
+        // Fields for sorting results by. Deprecation is 1 or 0, and the full
+        // name is repeated so that download counts can be looked up by it.
+        schema_builder.add_u64_field("deprecated", FAST);
+        schema_builder.add_bytes_field("fast_id", BytesOptions::default().set_fast());
+
+        // Exact, lowercased terms for the filters that can be used in queries,
+        // like `realm:server`.
+        for filter in Filter::ALL {
+            schema_builder.add_text_field(filter.field(), STRING);
+        }
 
         let schema = schema_builder.build();
-        let index = Index::create_in_ram(schema.clone());
+        let index = match path {
+            Some(path) => open_on_disk(path, &schema)?,
+            None => Index::create_in_ram(schema.clone()),
+        };
 
         let analyzer = TextAnalyzer::from(NgramTokenizer::all_ngrams(2, 10)).filter(LowerCaser);
         index.tokenizers().register("ngram", analyzer);
@@ -53,104 +120,255 @@ impl SearchBackend {
         let scope = schema.get_field("scope").unwrap();
         let name = schema.get_field("name").unwrap();
         let description = schema.get_field("description").unwrap();
+        let keywords = schema.get_field("keywords").unwrap();
+        let tags = schema.get_field("tags").unwrap();
 
-        let mut query_parser = QueryParser::for_index(&index, vec![scope, name, description]);
+        let mut query_parser =
+            QueryParser::for_index(&index, vec![scope, name, description, keywords, tags]);
         query_parser.set_conjunction_by_default();
         query_parser.set_field_boost(scope, 3.0);
         query_parser.set_field_boost(name, 5.0);
+        query_parser.set_field_boost(keywords, 2.0);
+        query_parser.set_field_boost(tags, 2.0);
 
         let mut backend = Self {
             schema,
-            writer,
+            writer: Arc::new(Mutex::new(writer)),
             reader,
             query_parser,
+            watermark_path: path.map(|path| path.join(WATERMARK_FILE)),
+        };
+
+        let up_to_date = match (&backend.watermark_path, index_head(package_index.path())) {
+            (Some(watermark_path), Some(commit)) => {
+                read_watermark(watermark_path)
+                    == Some(Watermark {
+                        schema: SCHEMA_VERSION,
+                        commit,
+                    })
+            }
+            _ => false,
         };
 
-        backend.crawl_packages(package_index)?;
+        if up_to_date {
+            println!("Search index is up to date with the package index");
+        } else {
+            backend.crawl_packages(package_index)?;
+        }
+
         Ok(backend)
     }
 
+    /// Rebuild the search index from every package in the index.
     pub fn crawl_packages(&mut self, package_index: &PackageIndex) -> anyhow::Result<()> {
-        let scope = self.schema.get_field("scope").unwrap();
-        let name = self.schema.get_field("name").unwrap();
-        let versions = self.schema.get_field("versions").unwrap();
-        let description = self.schema.get_field("description").unwrap();
-
-        println!("Crawling index...");
-        let now = Instant::now();
-        self.writer.delete_all_documents()?;
-
-        for entry in WalkDir::new(package_index.path())
-            .min_depth(1)
-            .into_iter()
-            .filter_entry(|e| !is_config(e))
-        {
-            let entry = entry?;
-            let path = entry.path();
-
-            if path.is_dir() {
-                continue;
+        // A search index on disk knows which commit it was last up to date
+        // with, so the publish times in it can be kept.
+        let since = self
+            .watermark_path
+            .as_deref()
+            .and_then(read_watermark)
+            .filter(|watermark| watermark.schema == SCHEMA_VERSION)
+            .map(|watermark| watermark.commit);
+
+        crawl(
+            &self.schema,
+            &self.writer,
+            &self.reader,
+            package_index.path(),
+            since.as_deref(),
+            self.watermark_path.as_deref(),
+        )?;
+        self.reader.reload()?;
+
+        Ok(())
+    }
+
+    /// Bring the document for a single package up to date with the index,
+    /// like after it's published or deprecated. Packages that are no longer
+    /// in the index are removed from search.
+    ///
+    /// Committing to the search index can take a while, so the update is done
+    /// on a blocking task. Searches keep using the old document until it's
+    /// done.
+    pub fn update_package(
+        &self,
+        package_index: &PackageIndex,
+        name: &PackageName,
+    ) -> JoinHandle<anyhow::Result<()>> {
+        let schema = self.schema.clone();
+        let writer = Arc::clone(&self.writer);
+        let reader = self.reader.clone();
+        let watermark_path = self.watermark_path.clone();
+        let index_path = package_index.path().clone();
+        let name = name.clone();
+
+        task::spawn_blocking(move || {
+            update_package(&schema, &writer, &reader, &index_path, &name)?;
+            reader.reload()?;
+
+            match watermark_path {
+                Some(watermark_path) => advance_watermark(&watermark_path, &index_path, &name),
+                None => Ok(()),
             }
+        })
+    }
 
-            let package_scope = path
-                .parent()
-                .unwrap()
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap();
-            let package_name = path.file_name().unwrap().to_str().unwrap();
+    /// Crawl the whole index again every `interval`, as a safety net for
+    /// changes that never made it into search, like ones pushed straight to
+    /// the index repository.
+    pub fn spawn_rebuilds(&self, index_path: PathBuf, interval: Duration) {
+        let schema = self.schema.clone();
+        let reader = self.reader.clone();
+        let watermark_path = self.watermark_path.clone();
 
-            let metadata = package_index
-                .get_package_metadata(&PackageName::new(package_scope, package_name)?)?;
+        // The search index is up to date by the time rebuilds are started.
+        let mut since = index_head(&index_path);
 
-            let mut doc = Document::default();
+        // Only hold onto the writer weakly, so that it's released once the
+        // backend is dropped, along with the lock on an index on disk.
+        let writer = Arc::downgrade(&self.writer);
 
-            for manifest in &(*metadata).versions {
-                doc.add_text(versions, manifest.package.version.to_string());
+        // Readers pick up the new documents by themselves once they're
+        // committed.
+        thread::spawn(move || loop {
+            thread::sleep(interval);
 
-                if !manifest.package.version.is_prerelease() {
-                    doc.add_text(scope, manifest.package.name.scope());
-                    doc.add_text(name, manifest.package.name.name());
+            let writer = match Weak::upgrade(&writer) {
+                Some(writer) => writer,
+                None => return,
+            };
 
-                    if let Some(description_text) = &manifest.package.description {
-                        doc.add_text(description, description_text);
-                    }
+            let crawled = crawl(
+                &schema,
+                &writer,
+                &reader,
+                &index_path,
+                since.as_deref(),
+                watermark_path.as_deref(),
+            );
 
-                    break;
-                }
+            match crawled {
+                Ok(head) => since = head,
+                Err(err) => println!("Failed to rebuild search index: {:?}", err),
             }
+        });
+    }
+
+    /// Search for packages. Filters like `realm:server` can be mixed in with
+    /// the words of the query, and every result has to match all of them.
+    pub fn search(
+        &self,
+        query_input: &str,
+        options: &SearchOptions,
+        downloads: &DownloadCounts,
+    ) -> anyhow::Result<Vec<DocResult>> {
+        let (text, filters) = parse_query(query_input);
 
-            self.writer.add_document(doc);
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        if !text.is_empty() {
+            clauses.push((Occur::Must, self.query_parser.parse_query(&text)?));
         }
 
-        self.writer.commit()?;
-        println!("Finished crawling in {}ms", now.elapsed().as_millis());
+        for (filter, term) in filters {
+            let field = self.schema.get_field(filter.field()).unwrap();
+            let query = TermQuery::new(
+                Term::from_field_text(field, &term),
+                IndexRecordOption::Basic,
+            );
 
-        Ok(())
-    }
+            // Filters only narrow down the results, they shouldn't change how
+            // relevant each one is.
+            clauses.push((Occur::Must, Box::new(BoostQuery::new(Box::new(query), 0.0))));
+        }
+
+        let query: Box<dyn Query> = if clauses.is_empty() {
+            Box::new(AllQuery)
+        } else {
+            Box::new(BooleanQuery::new(clauses))
+        };
 
-    pub fn search(&self, query_input: &str) -> tantivy::Result<Vec<DocResult>> {
         let searcher = self.reader.searcher();
-        let query = self
-            .query_parser
-            .parse_query(&query_input.replace("/", " "))?;
-        let top_docs = searcher.search(&query, &TopDocs::with_limit(DOC_LIMIT))?;
+        let limit = options.limit.min(DOC_LIMIT);
+        let offset = options.offset.min(searcher.num_docs() as usize);
+
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let sort = options.sort;
+        let deprecated_field = self.schema.get_field("deprecated").unwrap();
+        let updated_field = self.schema.get_field("updated").unwrap();
+        let id_field = self.schema.get_field("fast_id").unwrap();
 
-        let mut docs = Vec::with_capacity(DOC_LIMIT);
+        // Downloads are counted all the time, so they're looked up while
+        // sorting rather than kept in the search index.
+        let download_counts = Arc::new(match sort {
+            SearchSort::Downloads => downloads.all(),
+            _ => BTreeMap::new(),
+        });
 
-        for (_score, doc_address) in top_docs {
+        // Only the matches up to the end of the requested page are kept, in
+        // order of their fast fields. Deprecated packages still show up, but
+        // after every package that isn't, and ties stay ordered by relevance.
+        let collector = TopDocs::with_limit(offset + limit).tweak_score(
+            move |segment_reader: &SegmentReader| {
+                let fast_fields = segment_reader.fast_fields();
+                let deprecated = fast_fields
+                    .u64(deprecated_field)
+                    .expect("deprecated should be a fast field");
+                let updated = fast_fields
+                    .u64(updated_field)
+                    .expect("updated should be a fast field");
+                let ids = fast_fields
+                    .bytes(id_field)
+                    .expect("fast_id should be a fast field");
+                let download_counts = Arc::clone(&download_counts);
+
+                move |doc: DocId, score: Score| {
+                    let key = match sort {
+                        SearchSort::Relevance => 0,
+                        SearchSort::Recent => updated.get(doc),
+                        SearchSort::Downloads => std::str::from_utf8(ids.get_bytes(doc))
+                            .ok()
+                            .and_then(|id| download_counts.get(id))
+                            .copied()
+                            .unwrap_or(0),
+                    };
+
+                    (deprecated.get(doc) == 0, key, score)
+                }
+            },
+        );
+        let top_docs = searcher.search(&query, &collector)?;
+
+        let mut docs = Vec::with_capacity(limit);
+
+        for (_key, doc_address) in top_docs.into_iter().skip(offset) {
             let retrieved_doc = searcher.doc(doc_address)?;
             let retrieved_doc = self.schema.to_json(&retrieved_doc);
             let retrieved_doc: NativeDocResult = match serde_json::from_str(&retrieved_doc) {
                 Ok(doc) => doc,
                 Err(_) => continue,
             };
+            let scope = retrieved_doc.scope[0].clone();
+            let name = retrieved_doc.name[0].clone();
+            let downloads = downloads.get(&format!("{}/{}", scope, name));
+
             docs.push(DocResult {
-                scope: retrieved_doc.scope[0].clone(),
-                name: retrieved_doc.name[0].clone(),
+                scope,
+                name,
                 versions: retrieved_doc.versions,
                 description: retrieved_doc.description.map(|d| d[0].clone()),
+                realm: retrieved_doc.realm.map(|r| r[0].clone()),
+                license: retrieved_doc.license.map(|l| l[0].clone()),
+                authors: retrieved_doc.authors.unwrap_or_default(),
+                keywords: retrieved_doc.keywords.unwrap_or_default(),
+                tags: retrieved_doc.tags.unwrap_or_default(),
+                downloads,
+                updated_at: retrieved_doc.updated.map(|u| u[0]),
+                deprecation: retrieved_doc
+                    .deprecation
+                    .and_then(|d| serde_json::from_str(&d[0]).ok()),
             });
         }
 
@@ -158,6 +376,683 @@ impl SearchBackend {
     }
 }
 
+/// How search results are ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchSort {
+    /// Best matches for the query first.
+    Relevance,
+
+    /// Most recently published first.
+    Recent,
+
+    /// Most downloaded first.
+    Downloads,
+}
+
+impl FromStr for SearchSort {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        Ok(match value {
+            "relevance" => Self::Relevance,
+            "recent" => Self::Recent,
+            "downloads" => Self::Downloads,
+            _ => bail!(
+                "unknown sort '{}', expected 'relevance', 'recent' or 'downloads'",
+                value
+            ),
+        })
+    }
+}
+
+/// Which page of results to return, and how they're sorted.
+pub struct SearchOptions {
+    pub limit: usize,
+    pub offset: usize,
+    pub sort: SearchSort,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            limit: DOC_LIMIT,
+            offset: 0,
+            sort: SearchSort::Relevance,
+        }
+    }
+}
+
+/// Something search results can be narrowed down by, written as `key:value`
+/// in a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    Scope,
+    Realm,
+    License,
+    Author,
+}
+
+impl Filter {
+    const ALL: [Filter; 4] = [Self::Scope, Self::Realm, Self::License, Self::Author];
+
+    fn key(self) -> &'static str {
+        match self {
+            Self::Scope => "scope",
+            Self::Realm => "realm",
+            Self::License => "license",
+            Self::Author => "author",
+        }
+    }
+
+    /// The field holding the terms this filter matches against.
+    fn field(self) -> &'static str {
+        match self {
+            Self::Scope => "scope_filter",
+            Self::Realm => "realm_filter",
+            Self::License => "license_filter",
+            Self::Author => "author_filter",
+        }
+    }
+
+    /// Turn a value given in a query into the terms a package has to have.
+    fn terms(self, value: &str) -> Vec<String> {
+        match self {
+            Self::Author => words(value).collect(),
+            _ => vec![value.to_lowercase()],
+        }
+    }
+}
+
+/// Split filters out of a query, leaving the words that should be searched
+/// for. Anything that looks like a filter but isn't one is left alone.
+fn parse_query(query_input: &str) -> (String, Vec<(Filter, String)>) {
+    let mut text = Vec::new();
+    let mut filters = Vec::new();
+
+    for word in query_input.split_whitespace() {
+        let filter = word.split_once(':').and_then(|(key, value)| {
+            let filter = Filter::ALL
+                .iter()
+                .copied()
+                .find(|filter| filter.key() == key)?;
+            if value.is_empty() {
+                None
+            } else {
+                Some((filter, value))
+            }
+        });
+
+        match filter {
+            Some((filter, value)) => {
+                for term in filter.terms(value) {
+                    filters.push((filter, term));
+                }
+            }
+            None => text.push(word.replace('/', " ")),
+        }
+    }
+
+    (text.join(" "), filters)
+}
+
+/// The licenses named in an SPDX expression, like `mit` and `apache-2.0` for
+/// `MIT OR Apache-2.0`.
+fn license_terms(license: &str) -> impl Iterator<Item = String> + '_ {
+    license
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|word| !word.is_empty() && !matches!(*word, "OR" | "AND" | "WITH"))
+        .map(str::to_lowercase)
+}
+
+/// The lowercased words in some text, like `biff` and `lumfer` for an author.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Rebuild the search index from every package in the index, returning the
+/// commit it's now up to date with. `since` is the commit it was last up to
+/// date with, if it's known.
+fn crawl(
+    schema: &Schema,
+    writer: &Mutex<IndexWriter>,
+    reader: &IndexReader,
+    index_path: &Path,
+    since: Option<&str>,
+    watermark_path: Option<&Path>,
+) -> anyhow::Result<Option<String>> {
+    println!("Crawling index...");
+    let now = Instant::now();
+
+    // Look at the commit before crawling, so that changes made while we're
+    // crawling aren't counted as included.
+    let head = index_head(index_path);
+
+    let mut names = Vec::new();
+    for entry in WalkDir::new(index_path)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !is_config(e))
+    {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_dir() {
+            continue;
+        }
+
+        let package_scope = path
+            .parent()
+            .unwrap()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let package_name = path.file_name().unwrap().to_str().unwrap();
+        names.push(PackageName::new(package_scope, package_name)?);
+    }
+
+    let updated = publish_times(&reader.searcher(), schema, index_path, since, &names)?;
+
+    let mut writer = writer.lock().unwrap();
+    writer.delete_all_documents()?;
+
+    for name in &names {
+        let updated = updated.get(&name.to_string()).copied();
+
+        if let Some(doc) = read_package_document(schema, index_path, name, updated)? {
+            writer.add_document(doc);
+        }
+    }
+
+    writer.commit()?;
+    println!("Finished crawling in {}ms", now.elapsed().as_millis());
+
+    if let (Some(watermark_path), Some(head)) = (watermark_path, &head) {
+        write_watermark(watermark_path, head)?;
+    }
+
+    Ok(head)
+}
+
+fn update_package(
+    schema: &Schema,
+    writer: &Mutex<IndexWriter>,
+    reader: &IndexReader,
+    index_path: &Path,
+    name: &PackageName,
+) -> anyhow::Result<()> {
+    let updated = updated_publish_time(&reader.searcher(), schema, index_path, name)?;
+
+    let mut writer = writer.lock().unwrap();
+    writer.delete_term(id_term(schema, name));
+
+    if let Some(doc) = read_package_document(schema, index_path, name, updated)? {
+        writer.add_document(doc);
+    }
+
+    writer.commit()?;
+    Ok(())
+}
+
+/// Move the watermark along after a package is updated, as long as the newest
+/// commit in the package index only changed that package and the search index
+/// was up to date before it. Otherwise the watermark is left behind, and the
+/// next start does a full crawl.
+fn advance_watermark(
+    watermark_path: &Path,
+    index_path: &Path,
+    name: &PackageName,
+) -> anyhow::Result<()> {
+    let watermark = match read_watermark(watermark_path) {
+        Some(watermark) => watermark,
+        None => return Ok(()),
+    };
+
+    let repo = Repository::open(index_path)?;
+    let head = repo.head()?.peel_to_commit()?;
+    let parent = match head.parents().next() {
+        Some(parent) if parent.id().to_string() == watermark.commit => parent,
+        _ => return Ok(()),
+    };
+
+    let package_files = [
+        Path::new(name.scope()).join(name.name()),
+        Path::new(name.scope()).join("deprecations.json"),
+        Path::new(name.scope()).join("owners.json"),
+    ];
+    let diff = repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&head.tree()?), None)?;
+    let only_this_package = diff.deltas().all(|delta| {
+        delta
+            .new_file()
+            .path()
+            .is_some_and(|path| package_files.iter().any(|file| file == path))
+    });
+
+    if only_this_package {
+        write_watermark(watermark_path, &head.id().to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Read a package straight from the index's files and turn it into a search
+/// document. The files are read directly rather than through `PackageIndex`,
+/// whose cache doesn't know about changes pulled from the remote.
+fn read_package_document(
+    schema: &Schema,
+    index_path: &Path,
+    name: &PackageName,
+    updated: Option<u64>,
+) -> anyhow::Result<Option<Document>> {
+    let package_path = index_path.join(name.scope()).join(name.name());
+
+    let file = match fs_err::File::open(&package_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let metadata = PackageMetadata {
+        versions: parse_package_versions(BufReader::new(file), name)?,
+        deprecation: read_deprecation(index_path, name)?,
+    };
+
+    Ok(Some(package_document(schema, name, &metadata, updated)?))
+}
+
+fn package_document(
+    schema: &Schema,
+    package_name: &PackageName,
+    metadata: &PackageMetadata,
+    updated: Option<u64>,
+) -> anyhow::Result<Document> {
+    let field = |name| schema.get_field(name).unwrap();
+
+    let mut doc = Document::default();
+    doc.add_text(field("id"), package_name.to_string());
+    doc.add_bytes(field("fast_id"), package_name.to_string().into_bytes());
+    doc.add_u64(field("deprecated"), metadata.deprecation.is_some() as u64);
+    doc.add_text(
+        field(Filter::Scope.field()),
+        package_name.scope().to_lowercase(),
+    );
+
+    if let Some(package_deprecation) = &metadata.deprecation {
+        doc.add_text(
+            field("deprecation"),
+            serde_json::to_string(package_deprecation)?,
+        );
+    }
+
+    if let Some(updated) = updated {
+        doc.add_u64(field("updated"), updated);
+    }
+
+    for manifest in &metadata.versions {
+        doc.add_text(field("versions"), manifest.package.version.to_string());
+    }
+
+    // Describe the package by its newest release, or its newest prerelease if
+    // it doesn't have any releases yet. Versions are sorted newest first.
+    let latest = metadata
+        .versions
+        .iter()
+        .find(|manifest| !manifest.package.version.is_prerelease())
+        .or_else(|| metadata.versions.first());
+
+    let package = match latest {
+        Some(manifest) => &manifest.package,
+        None => return Ok(doc),
+    };
+
+    doc.add_text(field("scope"), package.name.scope());
+    doc.add_text(field("name"), package.name.name());
+
+    if let Some(description) = &package.description {
+        doc.add_text(field("description"), description);
+    }
+
+    for keyword in &package.keywords {
+        doc.add_text(field("keywords"), keyword);
+    }
+
+    for tag in &package.tags {
+        doc.add_text(field("tags"), tag);
+    }
+
+    // Realms are named the same way they're written in manifests.
+    if let Some(realm) = serde_json::to_value(package.realm)?.as_str() {
+        doc.add_text(field("realm"), realm);
+        doc.add_text(field(Filter::Realm.field()), realm);
+    }
+
+    if let Some(license) = &package.license {
+        doc.add_text(field("license"), license);
+
+        for term in license_terms(license) {
+            doc.add_text(field(Filter::License.field()), term);
+        }
+    }
+
+    for author in &package.authors {
+        doc.add_text(field("authors"), author);
+
+        for term in words(author) {
+            doc.add_text(field(Filter::Author.field()), term);
+        }
+    }
+
+    Ok(doc)
+}
+
+/// When each of `names` was last published, keyed by `scope/name`.
+///
+/// Walking the whole history of the index on every crawl would get slower and
+/// slower, so times are kept from the search index for packages that haven't
+/// changed since `since`, the commit it was last up to date with. History only
+/// has to be walked back to there, except to find packages that the search
+/// index hasn't seen before.
+fn publish_times(
+    searcher: &Searcher,
+    schema: &Schema,
+    index_path: &Path,
+    since: Option<&str>,
+    names: &[PackageName],
+) -> anyhow::Result<HashMap<String, u64>> {
+    let repo = match Repository::open(index_path) {
+        Ok(repo) => repo,
+        Err(_) => return Ok(HashMap::new()),
+    };
+
+    let mut times = HashMap::new();
+    if let Some(since) = since {
+        if let Some(changed) = published_since(&repo, since)? {
+            times = indexed_publish_times(searcher, schema)?;
+            times.extend(changed);
+        }
+    }
+
+    let missing: HashSet<String> = names
+        .iter()
+        .map(PackageName::to_string)
+        .filter(|id| !times.contains_key(id))
+        .collect();
+
+    if !missing.is_empty() {
+        times.extend(last_published(&repo, &missing)?);
+    }
+
+    Ok(times)
+}
+
+/// When a package that was just changed in the index was last published.
+/// Right after it's published, that's the newest commit in the index.
+/// Otherwise, like after it's deprecated, it keeps the time it already had.
+fn updated_publish_time(
+    searcher: &Searcher,
+    schema: &Schema,
+    index_path: &Path,
+    name: &PackageName,
+) -> anyhow::Result<Option<u64>> {
+    let repo = match Repository::open(index_path) {
+        Ok(repo) => repo,
+        Err(_) => return Ok(None),
+    };
+    let head = match repo.head().ok().and_then(|head| head.peel_to_commit().ok()) {
+        Some(head) => head,
+        None => return Ok(None),
+    };
+
+    let id = name.to_string();
+    if published_in(&repo, &head)?.contains(&id) {
+        return Ok(Some(commit_time(&head)));
+    }
+
+    if let Some(time) = indexed_publish_times(searcher, schema)?.remove(&id) {
+        return Ok(Some(time));
+    }
+
+    let mut missing = HashSet::new();
+    missing.insert(id.clone());
+    Ok(last_published(&repo, &missing)?.remove(&id))
+}
+
+/// The publish times stored in the search index, keyed by `scope/name`.
+fn indexed_publish_times(
+    searcher: &Searcher,
+    schema: &Schema,
+) -> anyhow::Result<HashMap<String, u64>> {
+    let id_field = schema.get_field("fast_id").unwrap();
+    let updated_field = schema.get_field("updated").unwrap();
+
+    let mut times = HashMap::new();
+
+    for segment_reader in searcher.segment_readers() {
+        let fast_fields = segment_reader.fast_fields();
+        let ids = fast_fields
+            .bytes(id_field)
+            .context("could not read package ids from the search index")?;
+        let updated = fast_fields
+            .u64(updated_field)
+            .context("could not read publish times from the search index")?;
+
+        for doc in 0..segment_reader.max_doc() {
+            // Packages without a time are stored as 0.
+            let time = updated.get(doc);
+            if segment_reader.is_deleted(doc) || time == 0 {
+                continue;
+            }
+
+            if let Ok(id) = std::str::from_utf8(ids.get_bytes(doc)) {
+                times.insert(id.to_owned(), time);
+            }
+        }
+    }
+
+    Ok(times)
+}
+
+/// When each package changed after the commit `since` was last published.
+/// Nothing is known if `since` isn't in the index anymore, like after its
+/// history was rewritten.
+fn published_since(repo: &Repository, since: &str) -> anyhow::Result<Option<HashMap<String, u64>>> {
+    let since = match Oid::from_str(since) {
+        Ok(since) if repo.find_commit(since).is_ok() => since,
+        _ => return Ok(None),
+    };
+
+    let mut times = HashMap::new();
+    walk_index_history(repo, Some(since), |commit| {
+        for id in published_in(repo, commit)? {
+            times.entry(id).or_insert_with(|| commit_time(commit));
+        }
+
+        Ok(true)
+    })?;
+
+    Ok(Some(times))
+}
+
+/// When each package in `wanted` was last published, going by the newest
+/// commit that changed its file in the index. The walk through the index's
+/// history stops as soon as every one of them has been found.
+fn last_published(
+    repo: &Repository,
+    wanted: &HashSet<String>,
+) -> anyhow::Result<HashMap<String, u64>> {
+    let mut times = HashMap::new();
+
+    walk_index_history(repo, None, |commit| {
+        for id in published_in(repo, commit)? {
+            if wanted.contains(&id) {
+                times.entry(id).or_insert_with(|| commit_time(commit));
+            }
+        }
+
+        Ok(times.len() < wanted.len())
+    })?;
+
+    Ok(times)
+}
+
+/// Visit the commits in the index, newest first, leaving out `hide` and
+/// everything before it. `visit` returns whether to keep going.
+fn walk_index_history(
+    repo: &Repository,
+    hide: Option<Oid>,
+    mut visit: impl FnMut(&Commit<'_>) -> anyhow::Result<bool>,
+) -> anyhow::Result<()> {
+    let head = match repo.head().ok().and_then(|head| head.target()) {
+        Some(head) => head,
+        None => return Ok(()),
+    };
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.push(head)?;
+
+    if let Some(hide) = hide {
+        revwalk.hide(hide)?;
+    }
+
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+
+        if !visit(&commit)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// The packages whose files were changed by a commit, as `scope/name`.
+fn published_in(repo: &Repository, commit: &Commit<'_>) -> anyhow::Result<Vec<String>> {
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+    let mut ids = Vec::new();
+    for delta in diff.deltas() {
+        let path = match delta.new_file().path().and_then(Path::to_str) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        // Everything else at the top of a scope, like its owners, isn't a
+        // package.
+        if path.ends_with(".json") || path.matches('/').count() != 1 {
+            continue;
+        }
+
+        ids.push(path.to_owned());
+    }
+
+    Ok(ids)
+}
+
+fn commit_time(commit: &Commit<'_>) -> u64 {
+    commit.time().seconds().max(0) as u64
+}
+
+fn open_on_disk(path: &Path, schema: &Schema) -> anyhow::Result<Index> {
+    // An index made with another schema can't be opened, so start over.
+    let watermark = read_watermark(&path.join(WATERMARK_FILE));
+    let outdated = watermark.is_none_or(|watermark| watermark.schema != SCHEMA_VERSION);
+    if outdated && path.join("meta.json").exists() {
+        println!(
+            "Search index at {} is outdated, rebuilding it",
+            path.display()
+        );
+        remove_search_index(path)?;
+    }
+
+    fs_err::create_dir_all(path)?;
+    Ok(Index::open_or_create(
+        MmapDirectory::open(path)?,
+        schema.clone(),
+    )?)
+}
+
+/// Remove the files of a search index, refusing to touch a directory that has
+/// anything else in it, in case it was pointed somewhere it shouldn't be.
+fn remove_search_index(path: &Path) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+
+    for entry in fs_err::read_dir(path)? {
+        let entry = entry?;
+        let is_index_file = entry.file_type()?.is_file()
+            && entry.file_name().to_str().is_some_and(is_search_index_file);
+
+        if !is_index_file {
+            bail!(
+                "{} has files that aren't part of a search index, like {}. \
+                Move them out of the way, or use another search index path.",
+                path.display(),
+                entry.file_name().to_string_lossy()
+            );
+        }
+
+        files.push(entry.path());
+    }
+
+    for file in files {
+        fs_err::remove_file(file)?;
+    }
+
+    Ok(())
+}
+
+/// Whether a file could have been written by tantivy or by us, going by its
+/// name.
+fn is_search_index_file(name: &str) -> bool {
+    match name {
+        "meta.json"
+        | ".managed.json"
+        | ".tantivy-meta.lock"
+        | ".tantivy-writer.lock"
+        | WATERMARK_FILE => true,
+
+        // Segment files are named after the segment's UUID, with an extension
+        // for each of their parts.
+        _ => name
+            .split_once('.')
+            .is_some_and(|(id, _)| id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit())),
+    }
+}
+
+/// The commit the package index is at, if it's a Git repository with any
+/// commits.
+fn index_head(index_path: &Path) -> Option<String> {
+    let repo = Repository::open(index_path).ok()?;
+    let head = repo.head().ok()?.target()?;
+    Some(head.to_string())
+}
+
+fn read_watermark(path: &Path) -> Option<Watermark> {
+    let contents = fs_err::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn write_watermark(path: &Path, commit: &str) -> anyhow::Result<()> {
+    let watermark = Watermark {
+        schema: SCHEMA_VERSION,
+        commit: commit.to_owned(),
+    };
+
+    fs_err::write(path, serde_json::to_string(&watermark)?)?;
+    Ok(())
+}
+
+fn id_term(schema: &Schema, name: &PackageName) -> Term {
+    Term::from_field_text(schema.get_field("id").unwrap(), &name.to_string())
+}
+
 fn is_config(entry: &DirEntry) -> bool {
     entry
         .file_name()
@@ -172,6 +1067,13 @@ struct NativeDocResult {
     name: Vec<String>,
     versions: Vec<String>,
     description: Option<Vec<String>>,
+    deprecation: Option<Vec<String>>,
+    realm: Option<Vec<String>>,
+    license: Option<Vec<String>>,
+    authors: Option<Vec<String>>,
+    keywords: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+    updated: Option<Vec<u64>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -180,4 +1082,16 @@ pub struct DocResult {
     name: String,
     versions: Vec<String>,
     description: Option<String>,
+    realm: Option<String>,
+    license: Option<String>,
+    authors: Vec<String>,
+    keywords: Vec<String>,
+    tags: Vec<String>,
+    downloads: u64,
+
+    /// When the package was last published, in seconds since the Unix epoch.
+    updated_at: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deprecation: Option<Deprecation>,
 }
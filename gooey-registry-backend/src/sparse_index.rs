@@ -0,0 +1,110 @@
+//! Serves the package index over HTTP, so that clients can use the registry as
+//! a sparse registry and only fetch the index files they need.
+//!
+//! Every file gets an ETag made from a hash of its contents, letting clients
+//! revalidate their cached copies with `If-None-Match`.
+
+use std::convert::Infallible;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::format_err;
+use rocket::{
+    http::{ContentType, Status},
+    request::{FromRequest, Outcome},
+    response::Responder,
+    Request, Response,
+};
+
+use crate::error::{ApiErrorStatus, Error};
+
+/// The `If-None-Match` header of a request, if it had one.
+pub struct IfNoneMatch<'r>(Option<&'r str>);
+
+impl IfNoneMatch<'_> {
+    fn matches(&self, etag: &str) -> bool {
+        match self.0 {
+            Some(header) => header
+                .split(',')
+                .map(str::trim)
+                .any(|candidate| candidate == "*" || candidate == etag),
+            None => false,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch<'r> {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Infallible> {
+        Outcome::Success(IfNoneMatch(request.headers().get_one("If-None-Match")))
+    }
+}
+
+pub enum IndexFile {
+    NotModified {
+        etag: String,
+    },
+    Contents {
+        etag: String,
+        content_type: ContentType,
+        contents: Vec<u8>,
+    },
+}
+
+impl IndexFile {
+    /// Read a file from the index at `index_path`, unless the client already
+    /// has the current version of it.
+    pub fn read(
+        index_path: &Path,
+        path: &Path,
+        if_none_match: IfNoneMatch<'_>,
+    ) -> Result<Self, Error> {
+        let file_path = index_path.join(path);
+
+        if !file_path.is_file() {
+            return Err(
+                format_err!("{} is not in the index", path.display()).status(Status::NotFound)
+            );
+        }
+
+        let contents = fs_err::read(&file_path)?;
+        let etag = format!("\"{}\"", blake3::hash(&contents).to_hex());
+
+        if if_none_match.matches(&etag) {
+            return Ok(IndexFile::NotModified { etag });
+        }
+
+        let content_type = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ContentType::JSON,
+            _ => ContentType::Plain,
+        };
+
+        Ok(IndexFile::Contents {
+            etag,
+            content_type,
+            contents,
+        })
+    }
+}
+
+impl<'r> Responder<'r, 'static> for IndexFile {
+    fn respond_to(self, _request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            IndexFile::NotModified { etag } => Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", etag)
+                .ok(),
+            IndexFile::Contents {
+                etag,
+                content_type,
+                contents,
+            } => Response::build()
+                .sized_body(contents.len(), Cursor::new(contents))
+                .header(content_type)
+                .raw_header("ETag", etag)
+                .ok(),
+        }
+    }
+}
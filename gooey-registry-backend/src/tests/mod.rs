@@ -1,13 +1,15 @@
 use std::path::Path;
 
 use figment::{providers::Serialized, Figment};
-use libgooey::test_package::PackageBuilder;
+use libgooey::{manifest::Realm, test_package::PackageBuilder};
 use rocket::{
     http::{Accept, ContentType, Header, Status},
     local::blocking::{Client, LocalResponse},
 };
 
-use crate::{auth::AuthMode, config::Config, server, storage::StorageMode};
+use crate::{
+    auth::AuthMode, config::Config, index_storage::IndexMode, server, storage::StorageMode,
+};
 
 fn init_test_index_remote() -> anyhow::Result<url::Url> {
     let temp_dir = tempfile::tempdir()?;
@@ -49,18 +51,35 @@ fn new_client(auth: AuthMode) -> Client {
 }
 
 fn new_client_with_remote(auth: AuthMode, index_url: url::Url) -> Client {
+    new_client_with_index(auth, Some(index_url), IndexMode::Git)
+}
+
+fn new_client_with_index(auth: AuthMode, index_url: Option<url::Url>, index: IndexMode) -> Client {
+    new_client_with_config(test_config(auth, index_url, index))
+}
+
+fn test_config(auth: AuthMode, index_url: Option<url::Url>, index: IndexMode) -> Config {
     let package_path = tempfile::tempdir().unwrap().into_path();
     add_test_packages(&package_path).unwrap();
 
-    let figment = Figment::from(rocket::Config::default()).merge(Serialized::globals(Config {
+    Config {
         index_url,
+        index,
         storage: StorageMode::Local {
             path: Some(package_path),
         },
         auth,
+        api_key_label: None,
         github_token: None,
+        search_index_path: None,
+        search_rebuild_interval: None,
+        downloads_path: None,
         minimum_gooey_version: None,
-    }));
+    }
+}
+
+fn new_client_with_config(config: Config) -> Client {
+    let figment = Figment::from(rocket::Config::default()).merge(Serialized::globals(config));
 
     Client::tracked(server(figment)).expect("valid rocket instance")
 }
@@ -224,6 +243,86 @@ fn read_write_double_key() {
     .assert(response);
 }
 
+#[test]
+fn sparse_index() {
+    let contents = PackageBuilder::new("biff/hello@1.0.0").contents();
+
+    let client = new_client(AuthMode::ApiKey(String::from("hello")));
+    expect_status(
+        Status::Ok,
+        send_with_token(client.post("/v1/publish").body(contents.data()), "hello"),
+    );
+
+    let response = send_with_token(client.get("/v1/index/biff/hello"), "hello");
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_owned();
+    assert!(response.into_string().unwrap().contains("\"1.0.0\""));
+
+    let response = client
+        .get("/v1/index/biff/hello")
+        .header(Header::new("Authorization", "Bearer hello"))
+        .header(Header::new("If-None-Match", etag))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+
+    expect_status(
+        Status::Unauthorized,
+        client
+            .get("/v1/index/biff/hello")
+            .header(Accept::JSON)
+            .dispatch(),
+    );
+    expect_status(
+        Status::NotFound,
+        send_with_token(client.get("/v1/index/biff/goodbye"), "hello"),
+    );
+}
+
+#[test]
+fn local_index() {
+    let index_path = tempfile::tempdir().unwrap().into_path().join("index");
+    let mirror = init_test_index_remote().unwrap();
+    let client = new_client_with_index(
+        AuthMode::ApiKey(String::from("hello")),
+        None,
+        IndexMode::Local {
+            path: index_path,
+            api: Some("http://localhost:8000".parse().unwrap()),
+            mirror_url: Some(mirror.clone()),
+            mirror_interval: Some(1),
+        },
+    );
+
+    let response = client.get("/v1/index/config.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response
+        .into_string()
+        .unwrap()
+        .contains("http://localhost:8000"));
+
+    let contents = PackageBuilder::new("biff/hello@1.0.0").contents();
+    expect_status(
+        Status::Ok,
+        send_with_token(client.post("/v1/publish").body(contents.data()), "hello"),
+    );
+
+    let response = send_with_token(client.get("/v1/index/biff/hello"), "hello");
+    assert_eq!(response.status(), Status::Ok);
+
+    // The mirror catches up in the background.
+    let repo = git2::Repository::open(mirror.to_file_path().unwrap()).unwrap();
+    for _ in 0..20 {
+        let commit = repo.head().unwrap().peel_to_commit().unwrap();
+        if commit.message() == Some("Publish biff/hello@1.0.0") {
+            return;
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    }
+
+    panic!("the index was not mirrored");
+}
+
 #[test]
 fn publish_duplicate() {
     let contents = PackageBuilder::new("biff/hello@0.1.0").contents();
@@ -308,6 +407,523 @@ fn publish_then_publish_elsewhere() {
     .assert(response);
 }
 
+#[test]
+fn manage_scope_owners() {
+    let client = new_client(AuthMode::ApiKey(String::from("hello")));
+    let send_request = |request: rocket::local::blocking::LocalRequest<'_>| {
+        request
+            .header(Accept::JSON)
+            .header(Header::new("Authorization", "Bearer hello"))
+            .dispatch()
+    };
+    let expect = |status: Status, response: LocalResponse<'_>| {
+        Expectation {
+            status,
+            content_type: ContentType::JSON,
+        }
+        .assert(response)
+    };
+
+    expect(
+        Status::Ok,
+        send_request(client.put("/v1/scopes/biff/owners/1")),
+    );
+    expect(
+        Status::Ok,
+        send_request(client.put("/v1/scopes/biff/owners/2")),
+    );
+    expect(
+        Status::Conflict,
+        send_request(client.put("/v1/scopes/biff/owners/2")),
+    );
+
+    expect(
+        Status::Ok,
+        send_request(client.delete("/v1/scopes/biff/owners/1")),
+    );
+    expect(
+        Status::NotFound,
+        send_request(client.delete("/v1/scopes/biff/owners/1")),
+    );
+
+    // The last owner of a scope can't be removed
+    expect(
+        Status::BadRequest,
+        send_request(client.delete("/v1/scopes/biff/owners/2")),
+    );
+
+    let response = send_request(client.get("/v1/scopes/biff/owners"));
+    let owners: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(owners["owners"][0]["id"], 2);
+
+    // Ids aren't GitHub users without GitHub OAuth, so they don't have logins.
+    assert_eq!(owners["owners"][0]["login"], serde_json::Value::Null);
+}
+
+#[test]
+fn manage_scope_owners_unauthenticated_401() {
+    let client = new_client(AuthMode::Unauthenticated);
+    let response = client
+        .put("/v1/scopes/biff/owners/1")
+        .header(Accept::JSON)
+        .dispatch();
+
+    Expectation {
+        status: Status::Unauthorized,
+        content_type: ContentType::JSON,
+    }
+    .assert(response);
+}
+
+#[test]
+fn deprecate_package() {
+    let client = new_client(AuthMode::ApiKey(String::from("hello")));
+    let send_request = |request: rocket::local::blocking::LocalRequest<'_>| {
+        request
+            .header(Accept::JSON)
+            .header(Header::new("Authorization", "Bearer hello"))
+            .dispatch()
+    };
+    let expect = |status: Status, response: LocalResponse<'_>| {
+        Expectation {
+            status,
+            content_type: ContentType::JSON,
+        }
+        .assert(response)
+    };
+
+    let contents = PackageBuilder::new("biff/hello@1.0.0").contents();
+    expect(
+        Status::Ok,
+        send_request(client.post("/v1/publish").body(contents.data())),
+    );
+
+    // Packages that haven't been published can't be deprecated
+    expect(
+        Status::NotFound,
+        send_request(
+            client
+                .put("/v1/package-deprecation/biff/missing")
+                .header(ContentType::JSON)
+                .body(r#"{"message": "Gone"}"#),
+        ),
+    );
+
+    expect(
+        Status::Ok,
+        send_request(
+            client
+                .put("/v1/package-deprecation/biff/hello")
+                .header(ContentType::JSON)
+                .body(r#"{"message": "Use goodbye"}"#),
+        ),
+    );
+
+    // A package can't be its own replacement
+    expect(
+        Status::BadRequest,
+        send_request(
+            client
+                .put("/v1/package-deprecation/biff/hello")
+                .header(ContentType::JSON)
+                .body(r#"{"message": "Use goodbye", "replacement": "biff/hello"}"#),
+        ),
+    );
+
+    let response = send_request(client.get("/v1/package-metadata/biff/hello"));
+    let metadata: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(metadata["deprecation"]["message"], "Use goodbye");
+
+    expect(
+        Status::Ok,
+        send_request(client.delete("/v1/package-deprecation/biff/hello")),
+    );
+    expect(
+        Status::NotFound,
+        send_request(client.delete("/v1/package-deprecation/biff/hello")),
+    );
+}
+
+#[test]
+fn search_updates() {
+    let client = new_client(AuthMode::ApiKey(String::from("hello")));
+    let search = || -> serde_json::Value {
+        send_with_token(client.get("/v1/package-search?query=greeter"), "hello")
+            .into_json()
+            .unwrap()
+    };
+
+    assert_eq!(search(), serde_json::json!([]));
+
+    let contents = PackageBuilder::new("biff/greeter@1.0.0").contents();
+    expect_status(
+        Status::Ok,
+        send_with_token(client.post("/v1/publish").body(contents.data()), "hello"),
+    );
+
+    let results = search();
+    assert_eq!(results[0]["name"], "greeter");
+    assert_eq!(results[0]["versions"], serde_json::json!(["1.0.0"]));
+
+    expect_status(
+        Status::Ok,
+        send_with_token(
+            client
+                .put("/v1/package-deprecation/biff/greeter")
+                .header(ContentType::JSON)
+                .body(r#"{"message": "Use goodbye"}"#),
+            "hello",
+        ),
+    );
+
+    let results = search();
+    assert_eq!(results.as_array().unwrap().len(), 1);
+    assert_eq!(results[0]["deprecation"]["message"], "Use goodbye");
+}
+
+#[test]
+fn search_filters_and_pages() {
+    let client = new_client(AuthMode::ApiKey(String::from("hello")));
+    let search = |uri: &'static str| -> serde_json::Value {
+        send_with_token(client.get(uri), "hello")
+            .into_json()
+            .unwrap()
+    };
+    let names = |results: serde_json::Value| -> Vec<String> {
+        results
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["name"].as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    let packages = [
+        PackageBuilder::new("biff/greeter@1.0.0")
+            .with_license("MIT OR Apache-2.0")
+            .with_author("Biff Lumfer <biff@playadopt.me>")
+            .with_keyword("greeting"),
+        PackageBuilder::new("biff/farewell@1.0.0")
+            .with_realm(Realm::Server)
+            .with_license("Zlib")
+            .with_tag("greetings"),
+        PackageBuilder::new("lumfer/wave@0.1.0-beta").with_keyword("greeting"),
+    ];
+    for package in &packages {
+        expect_status(
+            Status::Ok,
+            send_with_token(
+                client.post("/v1/publish").body(package.contents().data()),
+                "hello",
+            ),
+        );
+    }
+
+    let mut all = names(search("/v1/package-search?query=greeting"));
+    all.sort();
+    assert_eq!(all, vec!["farewell", "greeter", "wave"]);
+
+    assert_eq!(
+        names(search("/v1/package-search?query=greeting%20realm:server")),
+        vec!["farewell"]
+    );
+    assert_eq!(
+        names(search("/v1/package-search?query=license:apache-2.0")),
+        vec!["greeter"]
+    );
+    assert_eq!(
+        names(search("/v1/package-search?query=author:lumfer")),
+        vec!["greeter"]
+    );
+
+    // Packages with only prereleases are still searchable.
+    let results = search("/v1/package-search?query=scope:lumfer");
+    assert_eq!(results[0]["versions"], serde_json::json!(["0.1.0-beta"]));
+    assert_eq!(results[0]["keywords"], serde_json::json!(["greeting"]));
+
+    let first_page = names(search("/v1/package-search?query=greeting&limit=2"));
+    let second_page = names(search("/v1/package-search?query=greeting&limit=2&offset=2"));
+    assert_eq!(first_page.len(), 2);
+    assert_eq!(second_page.len(), 1);
+    assert!(!first_page.contains(&second_page[0]));
+
+    for _ in 0..2 {
+        expect_status(
+            Status::Ok,
+            send_with_token(
+                client.get("/v1/package-contents/biff/farewell/1.0.0"),
+                "hello",
+            ),
+        );
+    }
+
+    let results = search("/v1/package-search?query=greeting&sort=downloads");
+    assert_eq!(results[0]["name"], "farewell");
+    assert_eq!(results[0]["downloads"], 2);
+
+    let results = search("/v1/package-search?query=greeting&sort=recent");
+    assert!(results[0]["updated_at"].is_u64());
+
+    expect_status(
+        Status::BadRequest,
+        send_with_token(
+            client.get("/v1/package-search?query=greeting&sort=sideways"),
+            "hello",
+        ),
+    );
+}
+
+#[test]
+fn search_index_on_disk() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let config = || {
+        let mut config = test_config(
+            AuthMode::ApiKey(String::from("hello")),
+            None,
+            IndexMode::Local {
+                path: dir.join("index"),
+                api: Some("http://localhost:8000".parse().unwrap()),
+                mirror_url: None,
+                mirror_interval: None,
+            },
+        );
+        config.search_index_path = Some(dir.join("search"));
+        config
+    };
+
+    let client = new_client_with_config(config());
+    let contents = PackageBuilder::new("biff/greeter@1.0.0").contents();
+    expect_status(
+        Status::Ok,
+        send_with_token(client.post("/v1/publish").body(contents.data()), "hello"),
+    );
+    drop(client);
+
+    // Publishing moved the watermark along, so the search index can be used
+    // as it is after a restart.
+    let head = git2::Repository::open(dir.join("index"))
+        .unwrap()
+        .head()
+        .unwrap()
+        .target()
+        .unwrap();
+    let watermark = std::fs::read_to_string(dir.join("search/gooey-watermark.json")).unwrap();
+    let watermark: serde_json::Value = serde_json::from_str(&watermark).unwrap();
+    assert_eq!(watermark["commit"], head.to_string());
+
+    let client = new_client_with_config(config());
+    let results: serde_json::Value =
+        send_with_token(client.get("/v1/package-search?query=greeter"), "hello")
+            .into_json()
+            .unwrap();
+    assert_eq!(results[0]["name"], "greeter");
+}
+
+#[test]
+#[should_panic(expected = "aren't part of a search index")]
+fn outdated_search_index_with_other_files() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let search_path = dir.join("search");
+    std::fs::create_dir_all(&search_path).unwrap();
+    std::fs::write(search_path.join("meta.json"), "{}").unwrap();
+    std::fs::write(search_path.join("notes.txt"), "Don't delete me!").unwrap();
+
+    let mut config = test_config(
+        AuthMode::ApiKey(String::from("hello")),
+        None,
+        IndexMode::Local {
+            path: dir.join("index"),
+            api: Some("http://localhost:8000".parse().unwrap()),
+            mirror_url: None,
+            mirror_interval: None,
+        },
+    );
+    config.search_index_path = Some(search_path);
+
+    new_client_with_config(config);
+}
+
+fn new_accounts_client() -> Client {
+    let path = tempfile::tempdir()
+        .unwrap()
+        .into_path()
+        .join("accounts.json");
+
+    new_client(AuthMode::Accounts {
+        path: Some(path),
+        admin_key: String::from("admin"),
+    })
+}
+
+fn send_with_token<'c>(
+    request: rocket::local::blocking::LocalRequest<'c>,
+    token: &str,
+) -> LocalResponse<'c> {
+    request
+        .header(Accept::JSON)
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .dispatch()
+}
+
+fn expect_status(status: Status, response: LocalResponse<'_>) {
+    Expectation {
+        status,
+        content_type: ContentType::JSON,
+    }
+    .assert(response)
+}
+
+fn create_token(client: &Client, auth_token: &str, body: &str) -> serde_json::Value {
+    let response = send_with_token(
+        client
+            .post("/v1/tokens")
+            .header(ContentType::JSON)
+            .body(body),
+        auth_token,
+    );
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json().unwrap()
+}
+
+#[test]
+fn accounts_scoped_tokens() {
+    let client = new_accounts_client();
+
+    let new_user = || {
+        send_with_token(
+            client
+                .post("/v1/users")
+                .header(ContentType::JSON)
+                .body(r#"{"name": "biff"}"#),
+            "admin",
+        )
+    };
+    expect_status(Status::Ok, new_user());
+    expect_status(Status::Conflict, new_user());
+
+    let token = create_token(
+        &client,
+        "admin",
+        r#"{"user": "biff", "actions": ["publish", "manage-tokens"]}"#,
+    );
+    let token = token["token"].as_str().unwrap();
+
+    let response = send_with_token(client.get("/v1/whoami"), token);
+    let identity: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(identity["kind"], "account");
+    assert_eq!(identity["name"], "biff");
+
+    // Users can claim the scope matching their name, but no other
+    let contents = PackageBuilder::new("biff/hello@1.0.0").contents();
+    expect_status(
+        Status::Ok,
+        send_with_token(client.post("/v1/publish").body(contents.data()), token),
+    );
+
+    let contents = PackageBuilder::new("other/hello@1.0.0").contents();
+    expect_status(
+        Status::Unauthorized,
+        send_with_token(client.post("/v1/publish").body(contents.data()), token),
+    );
+
+    // Tokens can't create tokens that are allowed to do more than they are
+    expect_status(
+        Status::Unauthorized,
+        send_with_token(
+            client
+                .post("/v1/tokens")
+                .header(ContentType::JSON)
+                .body(r#"{"actions": ["manage-owners"]}"#),
+            token,
+        ),
+    );
+
+    let deprecate_token = create_token(
+        &client,
+        token,
+        r#"{"actions": ["deprecate"], "scopes": ["biff"]}"#,
+    );
+    let deprecate_id = deprecate_token["info"]["id"].as_str().unwrap();
+    let deprecate_token = deprecate_token["token"].as_str().unwrap();
+
+    let contents = PackageBuilder::new("biff/hello@1.0.1").contents();
+    expect_status(
+        Status::Unauthorized,
+        send_with_token(
+            client.post("/v1/publish").body(contents.data()),
+            deprecate_token,
+        ),
+    );
+
+    let deprecate = || {
+        send_with_token(
+            client
+                .put("/v1/package-deprecation/biff/hello")
+                .header(ContentType::JSON)
+                .body(r#"{"message": "Use goodbye"}"#),
+            deprecate_token,
+        )
+    };
+    expect_status(Status::Ok, deprecate());
+
+    let response = send_with_token(client.get("/v1/tokens"), token);
+    let tokens: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(tokens["tokens"].as_array().unwrap().len(), 2);
+
+    // Revoked tokens stop working straight away
+    let revoke_uri = format!("/v1/tokens/{}", deprecate_id);
+    expect_status(
+        Status::Ok,
+        send_with_token(client.delete(revoke_uri.as_str()), token),
+    );
+    expect_status(Status::Unauthorized, deprecate());
+}
+
+#[test]
+fn whoami() {
+    let client = new_client(AuthMode::ApiKey(String::from("hello")));
+
+    let response = send_with_token(client.get("/v1/whoami"), "hello");
+    let identity: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(identity["kind"], "api-key");
+    assert_eq!(identity["name"], "API key");
+
+    expect_status(
+        Status::Unauthorized,
+        send_with_token(client.get("/v1/whoami"), "wrong"),
+    );
+}
+
+#[test]
+fn accounts_expired_token_401() {
+    let client = new_accounts_client();
+
+    expect_status(
+        Status::Ok,
+        send_with_token(
+            client
+                .post("/v1/users")
+                .header(ContentType::JSON)
+                .body(r#"{"name": "biff"}"#),
+            "admin",
+        ),
+    );
+
+    let token = create_token(
+        &client,
+        "admin",
+        r#"{"user": "biff", "actions": ["publish"], "expires-in-days": 0}"#,
+    );
+    let token = token["token"].as_str().unwrap();
+
+    let contents = PackageBuilder::new("biff/hello@1.0.0").contents();
+    expect_status(
+        Status::Unauthorized,
+        send_with_token(client.post("/v1/publish").body(contents.data()), token),
+    );
+}
+
 // TODO: Implement yanking
 #[test]
 #[ignore]
@@ -0,0 +1,148 @@
+//! Turns error responses from a registry's API into errors that say what went
+//! wrong and what to do about it.
+
+use anyhow::format_err;
+use reqwest::{blocking::Response, StatusCode};
+use serde::Deserialize;
+
+/// The body of an error response from the registry backend.
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    pub message: String,
+}
+
+/// The API request that failed, which decides how a status is explained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiOperation {
+    Publish,
+    Search,
+    Owners,
+    Deprecate,
+    Whoami,
+}
+
+/// Pass successful responses through, and turn anything else into an error.
+pub fn check_response(operation: ApiOperation, response: Response) -> anyhow::Result<Response> {
+    let status = response.status();
+
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().unwrap_or_default();
+    Err(response_error(operation, status, &body))
+}
+
+/// Build the error for a failed request from its status and body.
+pub fn response_error(operation: ApiOperation, status: StatusCode, body: &str) -> anyhow::Error {
+    // Older registries and proxies in front of them don't always respond with
+    // JSON, so fall back to the raw body.
+    let message = match serde_json::from_str::<ErrorResponse>(body) {
+        Ok(response) => response.message,
+        Err(_) => body.trim().to_owned(),
+    };
+
+    let details = if message.is_empty() {
+        format!("The registry responded with {}", status)
+    } else {
+        format!("The registry responded with {}: {}", status, message)
+    };
+
+    match explain(operation, status) {
+        Some(explanation) => format_err!("{}\n\n{}", explanation, details),
+        None => match operation {
+            ApiOperation::Publish => format_err!("Failed to publish package.\n\n{}", details),
+            ApiOperation::Search => format_err!("Failed to search.\n\n{}", details),
+            ApiOperation::Owners => format_err!("Failed to manage scope owners.\n\n{}", details),
+            ApiOperation::Deprecate => {
+                format_err!("Failed to change the package's deprecation.\n\n{}", details)
+            }
+            ApiOperation::Whoami => format_err!("Failed to look up your identity.\n\n{}", details),
+        },
+    }
+}
+
+fn explain(operation: ApiOperation, status: StatusCode) -> Option<&'static str> {
+    let explanation = match (operation, status) {
+        (ApiOperation::Publish, StatusCode::UNAUTHORIZED) => {
+            "You don't have permission to publish this package. Make sure you own its scope, \
+            or log in again with `gooey login`."
+        }
+        (ApiOperation::Search, StatusCode::UNAUTHORIZED) => {
+            "This registry requires you to log in before searching, use `gooey login`."
+        }
+        (ApiOperation::Owners, StatusCode::UNAUTHORIZED) => {
+            "Only owners of a scope can change its owners. \
+            Make sure you're logged in as one with `gooey login`."
+        }
+        (ApiOperation::Deprecate, StatusCode::UNAUTHORIZED) => {
+            "Only owners of a package's scope can deprecate it. \
+            Make sure you're logged in as one with `gooey login`."
+        }
+        (ApiOperation::Whoami, StatusCode::UNAUTHORIZED) => {
+            "The registry didn't accept your token. It may have expired or been revoked, \
+            log in again with `gooey login`."
+        }
+        (ApiOperation::Publish, StatusCode::CONFLICT) => {
+            "This version of the package has already been published. \
+            Bump the version in gooey.toml and try again."
+        }
+        (_, StatusCode::UPGRADE_REQUIRED) => {
+            "This registry requires a newer version of gooey. Update gooey and try again."
+        }
+        (ApiOperation::Publish, StatusCode::BAD_REQUEST) => {
+            "The registry could not accept the package archive. \
+            Run `gooey publish --dry-run` to check the package."
+        }
+        (ApiOperation::Search, StatusCode::BAD_REQUEST) => {
+            "The registry could not understand the search query."
+        }
+        _ => return None,
+    };
+
+    Some(explanation)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_status() {
+        let err = response_error(
+            ApiOperation::Publish,
+            StatusCode::CONFLICT,
+            r#"{"message": "package already exists in index"}"#,
+        )
+        .to_string();
+
+        assert!(err.contains("Bump the version"), "{}", err);
+        assert!(
+            err.contains("409 Conflict: package already exists"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn unknown_status() {
+        let err = response_error(
+            ApiOperation::Search,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "upstream timed out\n",
+        )
+        .to_string();
+
+        assert!(err.starts_with("Failed to search."), "{}", err);
+        assert!(err.ends_with(": upstream timed out"), "{}", err);
+    }
+
+    #[test]
+    fn empty_body() {
+        let err =
+            response_error(ApiOperation::Publish, StatusCode::UPGRADE_REQUIRED, "").to_string();
+
+        assert!(err.contains("newer version of gooey"), "{}", err);
+        assert!(err.ends_with("426 Upgrade Required"), "{}", err);
+    }
+}
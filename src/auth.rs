@@ -2,12 +2,17 @@
 //! registries.
 
 use std::collections::HashMap;
+use std::env;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use serde::{Deserialize, Serialize};
 use toml_edit::{table, value, Document, Item};
+use url::Url;
+
+use crate::git_util::GitCredentials;
 
 const DEFAULT_AUTH_TOML: &str = r#"
 # This is where gooey stores details for authenticating with registries.
@@ -17,9 +22,21 @@ const DEFAULT_AUTH_TOML: &str = r#"
 
 "#;
 
+/// Token used for every registry that doesn't have its own environment
+/// variable.
+pub const TOKEN_ENV_VAR: &str = "GOOEY_TOKEN";
+
 #[derive(Serialize, Deserialize)]
 pub struct AuthStore {
     pub tokens: HashMap<String, String>,
+
+    /// Credential helper programs, keyed by registry API like `tokens`.
+    #[serde(default)]
+    pub helpers: HashMap<String, String>,
+
+    /// Credentials for private index repositories, keyed by index URL.
+    #[serde(default)]
+    pub git: HashMap<String, GitCredentials>,
 }
 
 impl AuthStore {
@@ -37,31 +54,108 @@ impl AuthStore {
         Ok(auth)
     }
 
-    /// Simplifies the usecase of AuthStore::load()?.tokens.get(key)
-    /// If multiple tokens are needed you should use AuthStore::load()?.tokens instead
+    /// Forget every saved token, credential helper and set of Git
+    /// credentials.
+    pub fn clear() -> anyhow::Result<()> {
+        let path = file_path()?;
+
+        if path.exists() {
+            fs_err::write(&path, DEFAULT_AUTH_TOML)?;
+        }
+
+        Ok(())
+    }
+
+    /// Find the token to use for the registry with the given API URL. The
+    /// first of these that gives a token wins:
+    ///
+    /// 1. The registry's own environment variable, see [`token_env_var`]
+    /// 2. The `GOOEY_TOKEN` environment variable
+    /// 3. The credential helper configured for the registry
+    /// 4. The token saved by `gooey login`
     pub fn get_token(key: &str) -> anyhow::Result<Option<String>> {
+        Self::load()?.resolve_token(key, |name| env::var(name).ok())
+    }
+
+    fn resolve_token(
+        mut self,
+        key: &str,
+        get_env: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Option<String>> {
+        let from_env = get_env(&token_env_var(key)).or_else(|| get_env(TOKEN_ENV_VAR));
+
+        if let Some(token) = from_env.filter(|token| !token.is_empty()) {
+            return Ok(Some(token));
+        }
+
+        if let Some(helper) = self.helpers.get(key) {
+            if let Some(token) = run_helper(helper, key)? {
+                return Ok(Some(token));
+            }
+        }
+
         // As this auth store will only live as long as this function we can just remove the value
         // to give ownership to whatever needs it
-        Ok(Self::load()?.tokens.remove(key))
+        Ok(self.tokens.remove(key))
     }
 
     pub fn set_token(key: &str, token: Option<&str>) -> anyhow::Result<()> {
+        Self::set_entry("tokens", key, token.map(value).unwrap_or(Item::None))
+    }
+
+    /// Configure a credential helper program for a registry, see
+    /// [`AuthStore::get_token`].
+    pub fn set_helper(key: &str, helper: Option<&str>) -> anyhow::Result<()> {
+        Self::set_entry("helpers", key, helper.map(value).unwrap_or(Item::None))
+    }
+
+    /// Find the credentials to use when cloning or updating an index
+    /// repository. Indexes without stored credentials fall back to the
+    /// machine's Git configuration.
+    pub fn git_credentials(index_url: &Url) -> anyhow::Result<GitCredentials> {
+        Ok(Self::load()?
+            .git
+            .remove(index_url.as_str())
+            .unwrap_or_default())
+    }
+
+    pub fn set_git_credentials(
+        index_url: &str,
+        credentials: Option<&GitCredentials>,
+    ) -> anyhow::Result<()> {
+        let entry = match credentials {
+            Some(credentials) => {
+                let mut entry = table();
+
+                if let Some(token) = &credentials.token {
+                    entry["token"] = value(token.as_str());
+                }
+
+                if let Some(ssh_key) = &credentials.ssh_key {
+                    entry["ssh-key"] = value(ssh_key.display().to_string());
+                }
+
+                entry
+            }
+            None => Item::None,
+        };
+
+        Self::set_entry("git", index_url, entry)
+    }
+
+    fn set_entry(table_name: &str, key: &str, entry: Item) -> anyhow::Result<()> {
         let path = file_path()?;
         let contents = Self::contents(&path)?;
 
         let mut auth: Document = contents.parse().unwrap();
 
-        if !auth.as_table_mut().contains_table("tokens") {
-            auth["tokens"] = table();
+        if !auth.as_table_mut().contains_table(table_name) {
+            auth[table_name] = table();
         }
 
-        let tokens = auth.as_table_mut().entry("tokens");
+        let entries = auth.as_table_mut().entry(table_name);
 
-        if let Some(token) = token {
-            tokens[key] = value(token);
-        } else {
-            tokens[key] = Item::None;
-        }
+        entries[key] = entry;
 
         fs_err::create_dir_all(path.parent().unwrap())?;
         fs_err::write(&path, auth.to_string())?;
@@ -83,9 +177,165 @@ impl AuthStore {
     }
 }
 
+/// The environment variable holding the token for one registry, derived from
+/// its API URL. `https://api.example.com:8000/` uses
+/// `GOOEY_TOKEN_API_EXAMPLE_COM_8000`.
+pub fn token_env_var(api: &str) -> String {
+    let without_scheme = match api.find("://") {
+        Some(index) => &api[index + 3..],
+        None => api,
+    };
+
+    let mut name = String::from(TOKEN_ENV_VAR);
+    let parts = without_scheme
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty());
+
+    for part in parts {
+        name.push('_');
+        name.push_str(&part.to_ascii_uppercase());
+    }
+
+    name
+}
+
+/// Ask a credential helper for a registry's token. Helpers are run as
+/// `<helper> get <api>` and print the token on stdout. Printing nothing means
+/// the helper has no token for the registry.
+fn run_helper(helper: &str, api: &str) -> anyhow::Result<Option<String>> {
+    let output = Command::new(helper)
+        .arg("get")
+        .arg(api)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run credential helper `{}`", helper))?;
+
+    if !output.status.success() {
+        bail!(
+            "Credential helper `{}` failed for {} ({})",
+            helper,
+            api,
+            output.status
+        );
+    }
+
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("Credential helper `{}` printed an invalid token", helper))?;
+
+    Ok(stdout
+        .lines()
+        .next()
+        .map(|line| line.trim().to_owned())
+        .filter(|token| !token.is_empty()))
+}
+
 fn file_path() -> anyhow::Result<PathBuf> {
     let mut path = dirs::home_dir().context("Failed to find home directory")?;
     path.push(".gooey");
     path.push("auth.toml");
     Ok(path)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn store(tokens: &[(&str, &str)], helpers: &[(&str, &str)]) -> AuthStore {
+        let to_map = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+
+        AuthStore {
+            tokens: to_map(tokens),
+            helpers: to_map(helpers),
+            git: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn env_var_names() {
+        assert_eq!(
+            token_env_var("https://api.gooey.run/"),
+            "GOOEY_TOKEN_API_GOOEY_RUN"
+        );
+        assert_eq!(
+            token_env_var("http://localhost:8000/registry"),
+            "GOOEY_TOKEN_LOCALHOST_8000_REGISTRY"
+        );
+    }
+
+    #[test]
+    fn token_chain_order() {
+        let api = "https://api.gooey.run/";
+        let resolve = |env: &[(&str, &str)]| {
+            store(&[(api, "stored")], &[])
+                .resolve_token(api, |name| {
+                    env.iter()
+                        .find(|(key, _)| *key == name)
+                        .map(|(_, value)| value.to_string())
+                })
+                .unwrap()
+        };
+
+        assert_eq!(resolve(&[]).as_deref(), Some("stored"));
+        assert_eq!(
+            resolve(&[("GOOEY_TOKEN", "global")]).as_deref(),
+            Some("global")
+        );
+        assert_eq!(
+            resolve(&[
+                ("GOOEY_TOKEN", "global"),
+                ("GOOEY_TOKEN_API_GOOEY_RUN", "registry")
+            ])
+            .as_deref(),
+            Some("registry")
+        );
+
+        // Empty variables are ignored, which makes them easy to unset in CI
+        assert_eq!(resolve(&[("GOOEY_TOKEN", "")]).as_deref(), Some("stored"));
+    }
+
+    #[test]
+    fn git_credentials() {
+        let auth: AuthStore = toml::from_str(
+            r#"
+            [tokens]
+
+            [git."https://git.example.com/index"]
+            token = "git-token"
+            ssh-key = "/home/biff/.ssh/index"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            auth.git["https://git.example.com/index"],
+            GitCredentials {
+                token: Some("git-token".to_owned()),
+                ssh_key: Some(PathBuf::from("/home/biff/.ssh/index")),
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn credential_helper() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let helper = dir.path().join("helper");
+        fs_err::write(&helper, "#!/bin/sh\necho \"helped-$1-$2\"\n").unwrap();
+        fs_err::set_permissions(&helper, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let api = "https://api.gooey.run/";
+        let token = store(&[(api, "stored")], &[(api, helper.to_str().unwrap())])
+            .resolve_token(api, |_| None)
+            .unwrap();
+
+        assert_eq!(token.as_deref(), Some("helped-get-https://api.gooey.run/"));
+    }
+}
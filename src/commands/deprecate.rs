@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use reqwest::{blocking::Client, header::AUTHORIZATION, Method};
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::{
+    api_error::{self, ApiOperation},
+    auth::AuthStore,
+    package_compat,
+    package_index::Deprecation,
+    package_name::PackageName,
+    package_source,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Mark every version of a package as deprecated. Projects that depend on it
+/// are warned when they install or update.
+#[derive(Debug, StructOpt)]
+pub struct DeprecateSubcommand {
+    /// Path to a project to decide which registry to use
+    #[structopt(long = "project-path", default_value = ".")]
+    pub project_path: PathBuf,
+
+    /// Auth token to use
+    #[structopt(long = "token")]
+    pub token: Option<String>,
+
+    /// The package to deprecate, like `scope/name`.
+    pub package: PackageName,
+
+    /// Why the package is deprecated.
+    #[structopt(long = "message", required_unless = "undo")]
+    pub message: Option<String>,
+
+    /// A package to use instead, like `scope/name`.
+    #[structopt(long = "replacement")]
+    pub replacement: Option<PackageName>,
+
+    /// Remove the package's deprecation.
+    #[structopt(long = "undo", conflicts_with_all = &["message", "replacement"])]
+    pub undo: bool,
+}
+
+impl DeprecateSubcommand {
+    pub fn run(self) -> anyhow::Result<()> {
+        let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
+        let api = package_source::registry_config(&manifest.package.registry)?.api;
+
+        let token = match self.token {
+            Some(token) => token,
+            None => AuthStore::get_token(api.as_str())?
+                .context("Authentication is required to deprecate packages, use `gooey login`")?,
+        };
+
+        let path = format!(
+            "/v1/package-deprecation/{}/{}",
+            self.package.scope(),
+            self.package.name()
+        );
+
+        let client = Client::new();
+        let mut request = client
+            .request(
+                if self.undo {
+                    Method::DELETE
+                } else {
+                    Method::PUT
+                },
+                api.join(&path)?,
+            )
+            .header("accept", "application/json")
+            .header("gooey-Version", VERSION)
+            .header(AUTHORIZATION, format!("Bearer {}", token));
+
+        if let Some(message) = self.message {
+            request = request.json(&Deprecation {
+                message,
+                replacement: self.replacement,
+            });
+        }
+
+        let response = api_error::check_response(ApiOperation::Deprecate, request.send()?)?;
+        let response: MessageResponse = response.json()?;
+        println!("{}", response.message);
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct MessageResponse {
+    message: String,
+}
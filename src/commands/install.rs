@@ -10,10 +10,11 @@ use crate::installation::InstallationContext;
 use crate::lockfile::{LockPackage, Lockfile};
 use crate::package_compat;
 use crate::package_id::PackageId;
-use crate::package_source::{PackageSource, PackageSourceMap, Registry, TestRegistry};
+use crate::package_source::{PackageSource, PackageSourceMap, TestRegistry};
 use crate::resolution::resolve;
+use crate::sourcemap::SOURCEMAP_FILE_NAME;
 
-use super::GlobalOptions;
+use super::{DownloadOptions, GlobalOptions};
 
 /// Install all of the dependencies of this project. (cross-compatible with other package formats)
 #[derive(Debug, StructOpt)]
@@ -21,6 +22,13 @@ pub struct InstallSubcommand {
     /// Path to the project to install dependencies for.
     #[structopt(long = "project-path", default_value = ".")]
     pub project_path: PathBuf,
+
+    #[structopt(flatten)]
+    pub download: DownloadOptions,
+
+    /// Also add the installed packages to the project's Rojo sourcemap.
+    #[structopt(long = "sourcemap")]
+    pub sourcemap: bool,
 }
 
 impl InstallSubcommand {
@@ -30,17 +38,21 @@ impl InstallSubcommand {
         let lockfile = Lockfile::load(&self.project_path)?
             .unwrap_or_else(|| Lockfile::from_manifest(&manifest));
 
+        let download_config = self.download.download_config()?;
+
         let default_registry: Box<PackageSource> = if global.test_registry {
             Box::new(PackageSource::TestRegistry(TestRegistry::new(
                 &manifest.package.registry,
             )))
         } else {
-            Box::new(PackageSource::Registry(Registry::from_registry_spec(
+            Box::new(PackageSource::from_registry_spec(
                 &manifest.package.registry,
-            )?))
+                &download_config,
+            )?)
         };
 
-        let mut package_sources = PackageSourceMap::new(default_registry);
+        let mut package_sources =
+            PackageSourceMap::new(default_registry).with_download_config(download_config);
         package_sources.add_fallbacks()?;
 
         let mut try_to_use = BTreeSet::new();
@@ -76,6 +88,16 @@ impl InstallSubcommand {
             resolved.activated.len() - 1
         ));
 
+        for (package_id, deprecation) in resolved.deprecations(&package_sources) {
+            progress.println(format!(
+                "{} Deprecated {}{}: {}",
+                SetForegroundColor(Color::DarkYellow),
+                SetForegroundColor(Color::Reset),
+                package_id,
+                deprecation
+            ));
+        }
+
         let lockfile = Lockfile::from_resolve(&resolved);
         lockfile.save(&self.project_path)?;
 
@@ -90,12 +112,10 @@ impl InstallSubcommand {
             SetForegroundColor(Color::DarkGreen),
             SetForegroundColor(Color::Reset)
         ));
-        let root_package_id = PackageId::new(manifest.package.name, manifest.package.version);
-        let installation = InstallationContext::new(
-            &self.project_path,
-            manifest.place.shared_packages,
-            manifest.place.server_packages,
-        );
+        let root_package_id =
+            PackageId::new(manifest.package.name.clone(), manifest.package.version);
+        let installation =
+            InstallationContext::new(&self.project_path, manifest.place, manifest.install)?;
 
         installation.clean()?;
         progress.println(format!(
@@ -105,7 +125,16 @@ impl InstallSubcommand {
         ));
         progress.finish_and_clear();
 
-        installation.install(package_sources, root_package_id, resolved)?;
+        installation
+            .clone()
+            .install(package_sources, root_package_id, resolved)?;
+
+        if self.sourcemap {
+            installation.write_sourcemap(
+                manifest.package.name.name(),
+                &self.project_path.join(SOURCEMAP_FILE_NAME),
+            )?;
+        }
 
         Ok(())
     }
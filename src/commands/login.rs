@@ -1,15 +1,23 @@
+use std::collections::BTreeMap;
+use std::env;
 use std::path::{Path, PathBuf};
 use std::thread::sleep;
 use std::time::Duration;
 
-use anyhow::format_err;
+use anyhow::{bail, format_err};
 use opener;
 use reqwest::blocking::Client;
 use reqwest::Url;
 use serde::Deserialize;
 use structopt::StructOpt;
 
-use crate::{auth::AuthStore, package_compat, package_index::{PackageIndex, PackageIndexConfig}};
+use crate::{
+    auth::{token_env_var, AuthStore, TOKEN_ENV_VAR},
+    git_util::GitCredentials,
+    package_compat,
+    package_index::PackageIndexConfig,
+    package_source::{self, SparseRegistry},
+};
 
 /// Log into a registry.
 #[derive(Debug, StructOpt)]
@@ -23,6 +31,22 @@ pub struct LoginSubcommand {
     /// URL of the remote index to add an auth token for
     #[structopt(long = "api")]
     pub api: Option<String>,
+    /// Credential helper program to get tokens from instead of storing one.
+    /// It's run as `<helper> get <api>` and should print the token.
+    #[structopt(long = "helper", conflicts_with = "token")]
+    pub helper: Option<String>,
+    /// Git token to use when cloning the registry's index, for private indexes
+    #[structopt(long = "git-token", conflicts_with_all = &["token", "api", "helper"])]
+    pub git_token: Option<String>,
+    /// SSH private key to use when cloning the registry's index
+    #[structopt(long = "ssh-key", conflicts_with_all = &["token", "api", "helper"])]
+    pub ssh_key: Option<PathBuf>,
+    /// List the registries and indexes that have saved credentials
+    #[structopt(
+        long = "list",
+        conflicts_with_all = &["token", "api", "helper", "git-token", "ssh-key"]
+    )]
+    pub list: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -108,13 +132,123 @@ fn prompt_github_auth(api: url::Url, github_oauth_id: &str) -> anyhow::Result<()
 
 fn fetch_package_index_config(project_path: &Path) -> anyhow::Result<PackageIndexConfig> {
     let manifest = package_compat::load_backwards_compatible_package(project_path)?;
-    let registry = Url::parse(&manifest.package.registry)?;
-    let package_index = PackageIndex::new(&registry, None)?;
-    package_index.config()
+    package_source::registry_config(&manifest.package.registry)
+}
+
+fn list_logins() -> anyhow::Result<()> {
+    let auth = AuthStore::load()?;
+
+    let mut registries: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for api in auth.tokens.keys() {
+        registries.entry(api).or_default().push("token".to_owned());
+    }
+    for (api, helper) in &auth.helpers {
+        registries
+            .entry(api)
+            .or_default()
+            .push(format!("credential helper `{}`", helper));
+    }
+
+    let indexes: BTreeMap<&str, &GitCredentials> = auth
+        .git
+        .iter()
+        .map(|(index, credentials)| (index.as_str(), credentials))
+        .collect();
+
+    if registries.is_empty() && indexes.is_empty() {
+        println!("Not logged in to any registries");
+    }
+
+    if !registries.is_empty() {
+        println!("Registries:");
+        for (api, methods) in &registries {
+            let methods = methods.join(", ");
+            match env::var(token_env_var(api)) {
+                Ok(_) => println!(
+                    "  {} ({}, overridden by {})",
+                    api,
+                    methods,
+                    token_env_var(api)
+                ),
+                Err(_) => println!("  {} ({})", api, methods),
+            }
+        }
+    }
+
+    if !indexes.is_empty() {
+        println!("Index repositories:");
+        for (index, credentials) in &indexes {
+            let mut methods = Vec::new();
+            if credentials.token.is_some() {
+                methods.push("Git token".to_owned());
+            }
+            if let Some(ssh_key) = &credentials.ssh_key {
+                methods.push(format!("SSH key {}", ssh_key.display()));
+            }
+
+            println!("  {} ({})", index, methods.join(", "));
+        }
+    }
+
+    if env::var(TOKEN_ENV_VAR).is_ok() {
+        println!();
+        println!(
+            "{} is set and is used for registries without their own variable",
+            TOKEN_ENV_VAR
+        );
+    }
+
+    Ok(())
 }
 
 impl LoginSubcommand {
     pub fn run(self) -> anyhow::Result<()> {
+        if self.list {
+            return list_logins();
+        }
+
+        // Git credentials have to be saved without touching the index, since
+        // they might be needed to clone it in the first place.
+        if self.git_token.is_some() || self.ssh_key.is_some() {
+            let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
+            if SparseRegistry::is_sparse_spec(&manifest.package.registry) {
+                bail!(
+                    "{} is a sparse registry, which is fetched over HTTP rather than Git. \
+                    Use `gooey login --token` instead.",
+                    manifest.package.registry
+                );
+            }
+
+            let registry = Url::parse(&manifest.package.registry)?;
+
+            let ssh_key = match self.ssh_key {
+                Some(path) => Some(fs_err::canonicalize(path)?),
+                None => None,
+            };
+            let credentials = GitCredentials {
+                token: self.git_token,
+                ssh_key,
+            };
+
+            AuthStore::set_git_credentials(registry.as_str(), Some(&credentials))?;
+            println!("Saved Git credentials for {}", registry);
+            return Ok(());
+        }
+
+        if let Some(helper) = &self.helper {
+            let api = match &self.api {
+                Some(api) => api.clone(),
+                None => fetch_package_index_config(&self.project_path)?
+                    .api
+                    .to_string(),
+            };
+
+            // A saved token would never be used with a helper configured, so
+            // drop it rather than leave it lying around.
+            AuthStore::set_token(&api, None)?;
+            return AuthStore::set_helper(&api, Some(helper));
+        }
+
         match (self.token, self.api) {
             (Some(token), Some(api)) => AuthStore::set_token(&api, Some(&token)),
             (Some(token), None) => {
@@ -2,7 +2,11 @@ use std::path::PathBuf;
 
 use structopt::StructOpt;
 
-use crate::{auth::AuthStore, package_compat, package_index::PackageIndex};
+use crate::{
+    auth::AuthStore,
+    package_compat,
+    package_source::{self, SparseRegistry},
+};
 
 /// Log out of a registry.
 #[derive(Debug, StructOpt)]
@@ -10,16 +14,35 @@ pub struct LogoutSubcommand {
     /// Path to a project to decide how to logout
     #[structopt(long = "project-path", default_value = ".")]
     pub project_path: PathBuf,
+    /// Log out of every registry instead of just the project's
+    #[structopt(long = "all")]
+    pub all: bool,
 }
 
 impl LogoutSubcommand {
     pub fn run(self) -> anyhow::Result<()> {
+        if self.all {
+            let auth = AuthStore::load()?;
+            let count = auth.tokens.len() + auth.helpers.len() + auth.git.len();
+
+            AuthStore::clear()?;
+            println!("Removed {} saved credentials", count);
+            return Ok(());
+        }
+
         let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
-        let registry = url::Url::parse(&manifest.package.registry)?;
-        let package_index = PackageIndex::new(&registry, None)?;
-        let api = package_index.config()?.api;
+        let registry = &manifest.package.registry;
+        let api = package_source::registry_config(registry)?.api;
 
         AuthStore::set_token(api.as_str(), None)?;
+        AuthStore::set_helper(api.as_str(), None)?;
+
+        // Sparse registries are fetched over HTTP, so only Git indexes have
+        // Git credentials to forget.
+        if !SparseRegistry::is_sparse_spec(registry) {
+            let registry = url::Url::parse(registry)?;
+            AuthStore::set_git_credentials(registry.as_str(), None)?;
+        }
 
         Ok(())
     }
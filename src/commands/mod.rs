@@ -1,25 +1,35 @@
+mod deprecate;
 mod init;
 mod install;
 mod login;
 mod logout;
 mod manifest_to_json;
+mod owner;
 mod package;
 mod publish;
 mod search;
+mod sourcemap;
 mod update;
+mod whoami;
 
+pub use deprecate::DeprecateSubcommand;
 pub use init::InitSubcommand;
 pub use install::InstallSubcommand;
 pub use login::LoginSubcommand;
 pub use logout::LogoutSubcommand;
 pub use manifest_to_json::ManifestToJsonSubcommand;
+pub use owner::{OwnerAction, OwnerSubcommand};
 pub use package::PackageSubcommand;
 pub use publish::PublishSubcommand;
 pub use search::SearchSubcommand;
+pub use sourcemap::SourcemapSubcommand;
 pub use update::{PackageSpec, UpdateSubcommand};
+pub use whoami::WhoamiSubcommand;
 
 use structopt::StructOpt;
 
+use crate::config::{Config, DownloadConfig};
+
 #[derive(Debug, StructOpt)]
 #[structopt(about = env!("CARGO_PKG_DESCRIPTION"))]
 pub struct Args {
@@ -37,11 +47,15 @@ impl Args {
             Subcommand::Init(subcommand) => subcommand.run(),
             Subcommand::Login(subcommand) => subcommand.run(),
             Subcommand::Logout(subcommand) => subcommand.run(),
+            Subcommand::Whoami(subcommand) => subcommand.run(),
+            Subcommand::Owner(subcommand) => subcommand.run(),
+            Subcommand::Deprecate(subcommand) => subcommand.run(),
             Subcommand::Update(subcommand) => subcommand.run(self.global),
             Subcommand::Search(subcommand) => subcommand.run(),
             Subcommand::Package(subcommand) => subcommand.run(),
             Subcommand::Install(subcommand) => subcommand.run(self.global),
             Subcommand::ManifestToJson(subcommand) => subcommand.run(),
+            Subcommand::Sourcemap(subcommand) => subcommand.run(),
         }
     }
 }
@@ -78,6 +92,49 @@ impl Default for GlobalOptions {
     }
 }
 
+/// Options that control how packages are downloaded. Any option given here
+/// overrides the matching setting in `~/.gooey/config.toml`.
+#[derive(Debug, Default, StructOpt)]
+pub struct DownloadOptions {
+    /// Maximum number of packages to download at the same time.
+    #[structopt(long = "max-parallel-downloads")]
+    pub max_parallel_downloads: Option<usize>,
+
+    /// Timeout in seconds for each request made to a registry.
+    #[structopt(long = "timeout")]
+    pub timeout: Option<u64>,
+
+    /// Number of times to retry a request after a transient error.
+    #[structopt(long = "retries")]
+    pub retries: Option<u32>,
+}
+
+impl DownloadOptions {
+    /// Merge these options on top of the user's configuration file.
+    pub fn download_config(&self) -> anyhow::Result<DownloadConfig> {
+        let mut config = Config::load()?.download;
+
+        if let Some(max_parallel_downloads) = self.max_parallel_downloads {
+            config.max_parallel_downloads = max_parallel_downloads;
+        }
+
+        if let Some(timeout) = self.timeout {
+            config.timeout = timeout;
+        }
+
+        if let Some(retries) = self.retries {
+            config.retries = retries;
+        }
+
+        anyhow::ensure!(
+            config.max_parallel_downloads > 0,
+            "max-parallel-downloads must be at least 1"
+        );
+
+        Ok(config)
+    }
+}
+
 #[derive(Debug, StructOpt)]
 pub enum Subcommand {
     Init(InitSubcommand),
@@ -86,7 +143,11 @@ pub enum Subcommand {
     Publish(PublishSubcommand),
     Login(LoginSubcommand),
     Logout(LogoutSubcommand),
+    Whoami(WhoamiSubcommand),
+    Owner(OwnerSubcommand),
+    Deprecate(DeprecateSubcommand),
     Search(SearchSubcommand),
     Package(PackageSubcommand),
     ManifestToJson(ManifestToJsonSubcommand),
+    Sourcemap(SourcemapSubcommand),
 }
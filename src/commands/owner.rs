@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use reqwest::{blocking::Client, header::AUTHORIZATION, Method};
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::{
+    api_error::{self, ApiOperation},
+    auth::AuthStore,
+    package_compat,
+    package_name::validate_scope,
+    package_source,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Manage who owns a scope. Owners can publish packages in the scope and
+/// change its owners.
+#[derive(Debug, StructOpt)]
+pub struct OwnerSubcommand {
+    /// Path to a project to decide which registry to use
+    #[structopt(long = "project-path", default_value = ".")]
+    pub project_path: PathBuf,
+
+    /// Auth token to use
+    #[structopt(long = "token")]
+    pub token: Option<String>,
+
+    #[structopt(subcommand)]
+    pub action: OwnerAction,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum OwnerAction {
+    /// Add an owner to a scope, by GitHub login or user ID.
+    Add { scope: String, user: String },
+
+    /// Remove an owner from a scope, by GitHub login or user ID.
+    Remove { scope: String, user: String },
+
+    /// List the owners of a scope.
+    List { scope: String },
+}
+
+impl OwnerSubcommand {
+    pub fn run(self) -> anyhow::Result<()> {
+        let scope = match &self.action {
+            OwnerAction::Add { scope, .. }
+            | OwnerAction::Remove { scope, .. }
+            | OwnerAction::List { scope } => scope,
+        };
+        validate_scope(scope)?;
+
+        let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
+        let api = package_source::registry_config(&manifest.package.registry)?.api;
+
+        let token = match &self.token {
+            Some(token) => Some(token.clone()),
+            None => AuthStore::get_token(api.as_str())?,
+        };
+
+        let (method, path) = match &self.action {
+            OwnerAction::Add { scope, user } => {
+                (Method::PUT, format!("/v1/scopes/{}/owners/{}", scope, user))
+            }
+            OwnerAction::Remove { scope, user } => (
+                Method::DELETE,
+                format!("/v1/scopes/{}/owners/{}", scope, user),
+            ),
+            OwnerAction::List { scope } => (Method::GET, format!("/v1/scopes/{}/owners", scope)),
+        };
+
+        let is_list = method == Method::GET;
+        let mut request = Client::new()
+            .request(method, api.join(&path)?)
+            .header("accept", "application/json")
+            .header("gooey-Version", VERSION);
+
+        match token {
+            Some(token) => request = request.header(AUTHORIZATION, format!("Bearer {}", token)),
+            // Listing owners only needs read access, which is often public.
+            None if is_list => {}
+            None => bail!("Authentication is required to change scope owners, use `gooey login`"),
+        }
+
+        let response = api_error::check_response(ApiOperation::Owners, request.send()?)?;
+
+        if is_list {
+            let response: OwnersResponse = response
+                .json()
+                .context("registry returned an invalid list of owners")?;
+
+            println!("Owners of {}:", response.scope);
+            for owner in response.owners {
+                match owner.login {
+                    Some(login) => println!("  {} ({})", login, owner.id),
+                    None => println!("  {}", owner.id),
+                }
+            }
+        } else {
+            let response: MessageResponse = response.json()?;
+            println!("{}", response.message);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct OwnersResponse {
+    scope: String,
+    owners: Vec<Owner>,
+}
+
+#[derive(Deserialize)]
+struct Owner {
+    id: u64,
+    login: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MessageResponse {
+    message: String,
+}
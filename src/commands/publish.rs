@@ -1,4 +1,9 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Context};
 use structopt::StructOpt;
@@ -6,12 +11,29 @@ use ubyte::ToByteUnit;
 use url::Url;
 
 use crate::{
-    auth::AuthStore, package_compat, package_contents::PackageContents,
-    package_index::PackageIndex, GlobalOptions,
+    api_error::{self, ApiOperation},
+    auth::AuthStore,
+    manifest::Manifest,
+    package_compat,
+    package_contents::PackageContents,
+    package_id::PackageId,
+    package_index::{PackageIndex, PackageIndexConfig},
+    package_req::PackageReq,
+    package_source::{PackageSourceProvider, SparseRegistry},
+    package_validation::validate_dependency_realms,
+    workspace::{self, WorkspaceMember},
+    GlobalOptions,
 };
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// How often to check the index for a package we've just published.
+const INDEX_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long to wait for a published package to appear in the index before
+/// giving up on the rest of the workspace.
+const INDEX_WAIT_TIMEOUT: Duration = Duration::from_secs(120);
+
 /// Publish this project to a registry.
 #[derive(Debug, StructOpt)]
 pub struct PublishSubcommand {
@@ -22,44 +44,58 @@ pub struct PublishSubcommand {
     /// Auth token to use
     #[structopt(long = "token")]
     pub token: Option<String>,
+
+    /// Pack and validate the package and report what would be uploaded,
+    /// without publishing it.
+    #[structopt(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Publish every package found in the project path, in dependency order.
+    #[structopt(long = "workspace")]
+    pub workspace: bool,
+
+    /// Paths to several projects to publish together, in dependency order.
+    #[structopt(parse(from_os_str))]
+    pub projects: Vec<PathBuf>,
 }
 
 impl PublishSubcommand {
     pub fn run(self, global: GlobalOptions) -> anyhow::Result<()> {
+        if self.workspace || !self.projects.is_empty() {
+            return self.publish_workspace(global);
+        }
+
         let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
 
         if manifest.package.private {
             bail!("Cannot publish private package.");
         }
 
-        let index_url = if global.test_registry {
-            let index_path = Path::new(&manifest.package.registry)
-                .join("index")
-                .canonicalize()?;
-
-            Url::from_directory_path(index_path).unwrap()
-        } else {
-            Url::parse(&manifest.package.registry)?
-        };
-
-        let package_index = if global.use_temp_index {
-            PackageIndex::new_temp(&index_url, None)?
-        } else {
-            PackageIndex::new(&index_url, None)?
-        };
-
+        let package_index = open_index(&manifest, &global)?;
         let api = package_index.config()?.api;
+        validate_dependency_realms(&manifest, |req| package_index.query(req))?;
         let contents = PackageContents::pack_from_path(&self.project_path)?;
 
-        if contents.data().len() > 2.mebibytes() {
-            bail!("Package size exceeds 2MB. Reduce package size and try again.");
+        if self.dry_run {
+            if version_exists(&package_index, &manifest)? {
+                bail!(
+                    "{} already exists in {}. Bump the package version and try again.",
+                    manifest.package_id(),
+                    package_index.url()
+                );
+            }
+
+            print_contents_report(&contents)?;
+            println!(
+                "Dry run: {} was not published to {}",
+                manifest.package_id(),
+                package_index.url()
+            );
+
+            return Ok(());
         }
 
-        let auth = match self.token {
-            Some(token) => token,
-            None => AuthStore::get_token(api.as_str())?
-                .with_context(|| "Authentication is required to publish, use `gooey login`")?,
-        };
+        let auth = self.auth(&api)?;
 
         println!(
             "Publishing {} to {}",
@@ -73,22 +109,309 @@ impl PublishSubcommand {
             return Ok(());
         }
 
-        let client = reqwest::blocking::Client::new();
-        let response = client
-            .post(api.join("/v1/publish")?)
-            .header("accept", "application/json")
-            .header("gooey-Version", VERSION)
-            .bearer_auth(auth)
-            .body(contents.data().to_owned())
-            .send()?;
+        upload(&api, auth, &contents)?;
+        println!("Package published successfully!");
 
-        if response.status().is_success() {
-            println!("Package published successfully!");
+        Ok(())
+    }
+
+    /// Publish several packages, each after the packages it depends on. Stops
+    /// at the first package that fails to publish.
+    fn publish_workspace(&self, global: GlobalOptions) -> anyhow::Result<()> {
+        let mut members = if self.workspace {
+            workspace::find_members(&self.project_path)?
         } else {
-            println!("Error: {}", response.status());
-            println!("{}", response.text()?);
+            Vec::new()
+        };
+
+        for path in &self.projects {
+            members.push(WorkspaceMember::load(path)?);
         }
 
+        let members = workspace::publish_order(members)?;
+        let mut indexes = HashMap::new();
+        let mut summary = Vec::new();
+
+        for (position, member) in members.iter().enumerate() {
+            let package_id = member.manifest.package_id();
+
+            match self.publish_member(&global, member, &mut indexes) {
+                Ok(outcome) => summary.push((package_id, outcome)),
+                Err(err) => {
+                    summary.push((package_id.clone(), Outcome::Failed));
+                    summary.extend(
+                        members[position + 1..]
+                            .iter()
+                            .map(|member| (member.manifest.package_id(), Outcome::NotAttempted)),
+                    );
+                    print_summary(&summary);
+
+                    return Err(err.context(format!("Failed to publish {}", package_id)));
+                }
+            }
+        }
+
+        print_summary(&summary);
         Ok(())
     }
+
+    fn publish_member(
+        &self,
+        global: &GlobalOptions,
+        member: &WorkspaceMember,
+        indexes: &mut HashMap<String, PublishIndex>,
+    ) -> anyhow::Result<Outcome> {
+        let manifest = &member.manifest;
+
+        if manifest.package.private {
+            return Ok(Outcome::Private);
+        }
+
+        if !indexes.contains_key(&manifest.package.registry) {
+            let package_index = open_index(manifest, global)?;
+            indexes.insert(manifest.package.registry.clone(), package_index);
+        }
+        let package_index = &indexes[&manifest.package.registry];
+
+        if version_exists(package_index, manifest)? {
+            return Ok(Outcome::AlreadyPublished);
+        }
+
+        validate_dependency_realms(manifest, |req| package_index.query(req))?;
+        let contents = PackageContents::pack_from_path(&member.path)?;
+
+        if self.dry_run {
+            println!("{}:", manifest.package_id());
+            print_contents_report(&contents)?;
+            return Ok(Outcome::DryRun);
+        }
+
+        let api = package_index.config()?.api;
+        let auth = self.auth(&api)?;
+
+        println!(
+            "Publishing {} to {}",
+            manifest.package_id(),
+            package_index.url()
+        );
+
+        // Used by integration tests to ensure token handling is correct
+        if let Some(token) = &global.check_token {
+            assert!(token.eq(&auth));
+            return Ok(Outcome::Published);
+        }
+
+        upload(&api, auth, &contents)?;
+        wait_for_index(package_index, manifest)?;
+        Ok(Outcome::Published)
+    }
+
+    fn auth(&self, api: &Url) -> anyhow::Result<String> {
+        match &self.token {
+            Some(token) => Ok(token.clone()),
+            None => AuthStore::get_token(api.as_str())?
+                .with_context(|| "Authentication is required to publish, use `gooey login`"),
+        }
+    }
+}
+
+/// What happened to each package when publishing several at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Published,
+    AlreadyPublished,
+    Private,
+    DryRun,
+    Failed,
+    NotAttempted,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            Outcome::Published => "published",
+            Outcome::AlreadyPublished => "skipped, already published",
+            Outcome::Private => "skipped, private",
+            Outcome::DryRun => "not published, dry run",
+            Outcome::Failed => "failed",
+            Outcome::NotAttempted => "not attempted",
+        };
+
+        formatter.write_str(description)
+    }
+}
+
+/// The index a package is published to. Publishing always goes through the
+/// registry's API, so the index is only read, to see whether a version is
+/// there yet.
+enum PublishIndex {
+    Git(PackageIndex),
+    Sparse(SparseRegistry),
+}
+
+impl PublishIndex {
+    fn config(&self) -> anyhow::Result<PackageIndexConfig> {
+        match self {
+            PublishIndex::Git(index) => index.config(),
+            PublishIndex::Sparse(registry) => registry.index_config(),
+        }
+    }
+
+    fn url(&self) -> String {
+        match self {
+            PublishIndex::Git(index) => index.url().to_string(),
+            PublishIndex::Sparse(registry) => registry.spec(),
+        }
+    }
+
+    /// The versions of a package in the index that match `package_req`.
+    fn query(&self, package_req: &PackageReq) -> anyhow::Result<Vec<Manifest>> {
+        match self {
+            PublishIndex::Git(index) => {
+                let metadata = index.get_package_metadata(package_req.name())?;
+
+                Ok(metadata
+                    .versions
+                    .iter()
+                    .filter(|manifest| {
+                        package_req.matches(&manifest.package.name, &manifest.package.version)
+                    })
+                    .cloned()
+                    .collect())
+            }
+            PublishIndex::Sparse(registry) => registry.query(package_req),
+        }
+    }
+
+    /// Forget anything read from the index about this package, so that the
+    /// next check sees the latest version of it.
+    fn refresh(&self, manifest: &Manifest) -> anyhow::Result<()> {
+        match self {
+            PublishIndex::Git(index) => {
+                index.update()?;
+                index.clear_cached_package(&manifest.package.name);
+                Ok(())
+            }
+            PublishIndex::Sparse(registry) => registry.update(),
+        }
+    }
+}
+
+fn open_index(manifest: &Manifest, global: &GlobalOptions) -> anyhow::Result<PublishIndex> {
+    if SparseRegistry::is_sparse_spec(&manifest.package.registry) && !global.test_registry {
+        let registry = SparseRegistry::from_registry_spec(&manifest.package.registry)?;
+        return Ok(PublishIndex::Sparse(registry));
+    }
+
+    let index_url = if global.test_registry {
+        let index_path = Path::new(&manifest.package.registry)
+            .join("index")
+            .canonicalize()?;
+
+        Url::from_directory_path(index_path).unwrap()
+    } else {
+        Url::parse(&manifest.package.registry)?
+    };
+
+    let credentials = AuthStore::git_credentials(&index_url)?;
+
+    let package_index = if global.use_temp_index {
+        PackageIndex::new_temp(&index_url, credentials)?
+    } else {
+        PackageIndex::new(&index_url, credentials)?
+    };
+
+    Ok(PublishIndex::Git(package_index))
+}
+
+fn upload(
+    api: &Url,
+    auth: String,
+    contents: &PackageContents,
+) -> anyhow::Result<reqwest::blocking::Response> {
+    let client = reqwest::blocking::Client::new();
+    let response = client
+        .post(api.join("/v1/publish")?)
+        .header("accept", "application/json")
+        .header("gooey-Version", VERSION)
+        .bearer_auth(auth)
+        .body(contents.data().to_owned())
+        .send()?;
+
+    api_error::check_response(ApiOperation::Publish, response)
+}
+
+/// Whether this version of the package has already been published to the
+/// index.
+fn version_exists(index: &PublishIndex, manifest: &Manifest) -> anyhow::Result<bool> {
+    let package_index = match index {
+        PublishIndex::Git(package_index) => package_index,
+        PublishIndex::Sparse(registry) => {
+            return registry.has_version(&manifest.package.name, &manifest.package.version)
+        }
+    };
+
+    match package_index.get_package_metadata(&manifest.package.name) {
+        Ok(metadata) => Ok(metadata
+            .versions
+            .iter()
+            .any(|version| version.package.version == manifest.package.version)),
+        Err(err) => match err.downcast_ref::<io::Error>() {
+            // The package has never been published.
+            Some(io_err) if io_err.kind() == io::ErrorKind::NotFound => Ok(false),
+            _ => Err(err),
+        },
+    }
+}
+
+/// Wait until a package we've just published shows up in the index, so that
+/// the packages depending on it can be published after it.
+fn wait_for_index(package_index: &PublishIndex, manifest: &Manifest) -> anyhow::Result<()> {
+    let started = Instant::now();
+
+    loop {
+        package_index.refresh(manifest)?;
+
+        if version_exists(package_index, manifest)? {
+            return Ok(());
+        }
+
+        if started.elapsed() > INDEX_WAIT_TIMEOUT {
+            bail!(
+                "{} was published, but did not appear in {} after {} seconds",
+                manifest.package_id(),
+                package_index.url(),
+                INDEX_WAIT_TIMEOUT.as_secs()
+            );
+        }
+
+        thread::sleep(INDEX_POLL_INTERVAL);
+    }
+}
+
+fn print_contents_report(contents: &PackageContents) -> anyhow::Result<()> {
+    let files = contents.files()?;
+    let total: u64 = files.iter().map(|(_, size)| size).sum();
+
+    for (name, size) in &files {
+        println!("{:>10}  {}", size.bytes().to_string(), name);
+    }
+
+    println!(
+        "{} files, {} ({} compressed)",
+        files.len(),
+        total.bytes(),
+        contents.data().len().bytes()
+    );
+
+    Ok(())
+}
+
+fn print_summary(summary: &[(PackageId, Outcome)]) {
+    println!();
+    println!("Summary:");
+
+    for (package_id, outcome) in summary {
+        println!("  {}: {}", package_id, outcome);
+    }
 }
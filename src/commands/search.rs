@@ -1,13 +1,18 @@
 use std::path::PathBuf;
 
-use anyhow::bail;
 use crossterm::style::Color;
 use crossterm::style::SetForegroundColor;
 use reqwest::{blocking::Client, header::AUTHORIZATION};
 use serde::Deserialize;
 use structopt::StructOpt;
 
-use crate::{auth::AuthStore, package_compat, package_index::PackageIndex};
+use crate::{
+    api_error::{self, ApiOperation},
+    auth::AuthStore,
+    package_compat,
+    package_index::Deprecation,
+    package_source,
+};
 
 /// Search a registry for packages matching a query.
 #[derive(Debug, StructOpt)]
@@ -17,38 +22,68 @@ pub struct SearchSubcommand {
     pub project_path: PathBuf,
 
     /// The query to be dispatched to the search endpoint
+    #[structopt(default_value = "")]
     pub query: String,
+
+    /// Only show packages in this scope
+    #[structopt(long = "scope")]
+    pub scope: Option<String>,
+
+    /// Only show packages for this realm: shared, server, client or dev
+    #[structopt(long = "realm")]
+    pub realm: Option<String>,
+
+    /// Only show packages available under this license, like `MIT`
+    #[structopt(long = "license")]
+    pub license: Option<String>,
+
+    /// Only show packages with an author matching this name
+    #[structopt(long = "author")]
+    pub author: Option<String>,
+
+    /// How many results to show, at most 100
+    #[structopt(long = "limit")]
+    pub limit: Option<usize>,
+
+    /// How many results to skip, for paging through them
+    #[structopt(long = "offset")]
+    pub offset: Option<usize>,
+
+    /// How to order results: relevance, recent or downloads
+    #[structopt(long = "sort")]
+    pub sort: Option<String>,
 }
 
 impl SearchSubcommand {
     pub fn run(self) -> anyhow::Result<()> {
         let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
-        let registry = url::Url::parse(&manifest.package.registry)?;
-        let auth_store = AuthStore::load()?;
-        let package_index = PackageIndex::new(&registry, None)?;
-        let api = package_index.config()?.api;
+        let api = package_source::registry_config(&manifest.package.registry)?.api;
 
-        let auth = auth_store.tokens.get(api.as_str());
+        let auth = AuthStore::get_token(api.as_str())?;
 
         let client = Client::new();
         let mut request = client
             .get(api.join("/v1/package-search/")?)
-            .query(&[("query", &self.query)]);
+            .query(&[("query", self.full_query())]);
 
-        if let Some(auth) = auth {
-            request = request.header(AUTHORIZATION, format!("Bearer {}", auth));
+        if let Some(limit) = self.limit {
+            request = request.query(&[("limit", limit)]);
+        }
+
+        if let Some(offset) = self.offset {
+            request = request.query(&[("offset", offset)]);
         }
 
-        let response = request.send()?;
+        if let Some(sort) = &self.sort {
+            request = request.query(&[("sort", sort)]);
+        }
 
-        if !response.status().is_success() {
-            bail!(
-                "Failed to search: {} {}",
-                response.status(),
-                response.text()?
-            );
+        if let Some(auth) = auth {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", auth));
         }
 
+        let response = api_error::check_response(ApiOperation::Search, request.send()?)?;
+
         let mut results: Vec<SearchResult> = response.json()?;
         println!();
 
@@ -70,8 +105,16 @@ impl SearchSubcommand {
                 );
             }
 
+            if result.deprecation.is_some() {
+                print!("{} deprecated", SetForegroundColor(Color::DarkYellow));
+            }
+
             println!("{}", SetForegroundColor(Color::Reset));
 
+            if let Some(deprecation) = &result.deprecation {
+                println!("    Deprecated: {}", deprecation);
+            }
+
             if let Some(description) = &result.description {
                 println!("    {}", description);
                 println!();
@@ -82,6 +125,28 @@ impl SearchSubcommand {
 
         Ok(())
     }
+
+    /// The query with any filters given as flags added to it, in the same
+    /// `key:value` form they can be written in the query itself.
+    fn full_query(&self) -> String {
+        let filters = [
+            ("scope", &self.scope),
+            ("realm", &self.realm),
+            ("license", &self.license),
+            ("author", &self.author),
+        ];
+
+        // Filters are one word each, so a value like an author's full name is
+        // split into a filter per word, all of which have to match.
+        let mut query = self.query.clone();
+        for (key, value) in filters.iter() {
+            for word in value.iter().flat_map(|value| value.split_whitespace()) {
+                query.push_str(&format!(" {}:{}", key, word));
+            }
+        }
+
+        query.trim().to_owned()
+    }
 }
 
 #[derive(Deserialize)]
@@ -90,4 +155,7 @@ struct SearchResult {
     pub name: String,
     pub versions: Vec<String>,
     pub description: Option<String>,
+
+    #[serde(default)]
+    pub deprecation: Option<Deprecation>,
 }
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use crate::installation::InstallationContext;
+use crate::package_compat;
+use crate::sourcemap::SOURCEMAP_FILE_NAME;
+
+/// Add the installed packages of this project to a Rojo sourcemap.
+///
+/// Entries are placed at the locations given in the `[place]` section of the
+/// manifest. Anything else in an existing sourcemap is kept as is.
+#[derive(Debug, StructOpt)]
+pub struct SourcemapSubcommand {
+    /// Path to the project to generate sourcemap entries for.
+    #[structopt(long = "project-path", default_value = ".")]
+    pub project_path: PathBuf,
+
+    /// Where to write the sourcemap. Defaults to `sourcemap.json` in the
+    /// project directory.
+    #[structopt(long = "output", short = "o")]
+    pub output: Option<PathBuf>,
+}
+
+impl SourcemapSubcommand {
+    pub fn run(self) -> anyhow::Result<()> {
+        let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
+
+        let output = match self.output {
+            Some(output) => output,
+            None => self.project_path.join(SOURCEMAP_FILE_NAME),
+        };
+
+        let installation =
+            InstallationContext::new(&self.project_path, manifest.place, manifest.install)?;
+
+        installation.write_sourcemap(manifest.package.name.name(), &output)
+    }
+}
@@ -9,8 +9,8 @@ use crate::package_compat;
 use crate::package_id::PackageId;
 use crate::package_name::PackageName;
 use crate::package_req::PackageReq;
-use crate::package_source::{PackageSource, PackageSourceMap, Registry, TestRegistry};
-use crate::{resolution, GlobalOptions};
+use crate::package_source::{PackageSource, PackageSourceMap, TestRegistry};
+use crate::{resolution, DownloadOptions, GlobalOptions};
 use crossterm::style::{Attribute, Color, SetAttribute, SetForegroundColor};
 use indicatif::{ProgressBar, ProgressStyle};
 use structopt::StructOpt;
@@ -25,6 +25,9 @@ pub struct UpdateSubcommand {
     /// An optional list of dependencies to update.
     /// They must be valid package name with an optional version requirement.
     pub package_specs: Vec<PackageSpec>,
+
+    #[structopt(flatten)]
+    pub download: DownloadOptions,
 }
 
 impl UpdateSubcommand {
@@ -36,17 +39,21 @@ impl UpdateSubcommand {
             None => Lockfile::from_manifest(&manifest),
         };
 
+        let download_config = self.download.download_config()?;
+
         let default_registry: Box<PackageSource> = if global.test_registry {
             Box::new(PackageSource::TestRegistry(TestRegistry::new(
                 &manifest.package.registry,
             )))
         } else {
-            Box::new(PackageSource::Registry(Registry::from_registry_spec(
+            Box::new(PackageSource::from_registry_spec(
                 &manifest.package.registry,
-            )?))
+                &download_config,
+            )?)
         };
 
-        let mut package_sources = PackageSourceMap::new(default_registry);
+        let mut package_sources =
+            PackageSourceMap::new(default_registry).with_download_config(download_config);
         package_sources.add_fallbacks()?;
 
         // If the user didn't specify any targets, then update all of the packages.
@@ -95,6 +102,16 @@ impl UpdateSubcommand {
             resolved_graph.activated.len() - 1
         ));
 
+        for (package_id, deprecation) in resolved_graph.deprecations(&package_sources) {
+            progress.println(format!(
+                "{} Deprecated {}{}: {}",
+                SetForegroundColor(Color::DarkYellow),
+                SetForegroundColor(Color::Reset),
+                package_id,
+                deprecation
+            ));
+        }
+
         progress.enable_steady_tick(Duration::from_millis(100));
         progress.suspend(|| {
             let dependency_changes =
@@ -111,11 +128,8 @@ impl UpdateSubcommand {
         ));
 
         let root_package_id = manifest.package_id();
-        let installation_context = InstallationContext::new(
-            &self.project_path,
-            manifest.place.shared_packages,
-            manifest.place.server_packages,
-        );
+        let installation_context =
+            InstallationContext::new(&self.project_path, manifest.place, manifest.install)?;
 
         progress.set_message(format!(
             "{}  Cleaning {}package destination...",
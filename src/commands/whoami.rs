@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use anyhow::format_err;
+use reqwest::{blocking::Client, header::AUTHORIZATION};
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::{
+    api_error::{self, ApiOperation},
+    auth::AuthStore,
+    package_compat, package_source,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Show who the registry thinks you are.
+#[derive(Debug, StructOpt)]
+pub struct WhoamiSubcommand {
+    /// Path to a project to decide which registry to use
+    #[structopt(long = "project-path", default_value = ".")]
+    pub project_path: PathBuf,
+
+    /// Auth token to use
+    #[structopt(long = "token")]
+    pub token: Option<String>,
+}
+
+impl WhoamiSubcommand {
+    pub fn run(self) -> anyhow::Result<()> {
+        let manifest = package_compat::load_backwards_compatible_package(&self.project_path)?;
+        let api = package_source::registry_config(&manifest.package.registry)?.api;
+
+        let token = match self.token {
+            Some(token) => token,
+            None => AuthStore::get_token(api.as_str())?
+                .ok_or_else(|| format_err!("Not logged in to {}, use `gooey login`", api))?,
+        };
+
+        let request = Client::new()
+            .get(api.join("/v1/whoami")?)
+            .header("accept", "application/json")
+            .header("gooey-Version", VERSION)
+            .header(AUTHORIZATION, format!("Bearer {}", token));
+
+        let response = api_error::check_response(ApiOperation::Whoami, request.send()?)?;
+        let identity: Identity = response.json()?;
+
+        println!("Logged in to {} as {}", api, identity);
+
+        if let Some(token) = identity.token {
+            if !token.scopes.is_empty() {
+                println!("Token is limited to scopes: {}", token.scopes.join(", "));
+            }
+            if !token.actions.is_empty() {
+                println!("Token can: {}", token.actions.join(", "));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Who a token belongs to, as described by the registry.
+#[derive(Debug, Deserialize)]
+struct Identity {
+    kind: String,
+    name: String,
+    id: Option<u64>,
+    token: Option<TokenDetails>,
+}
+
+impl std::fmt::Display for Identity {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.kind.as_str(), self.id) {
+            ("github", Some(id)) => write!(formatter, "{} (GitHub user {})", self.name, id),
+            ("account", Some(id)) => write!(formatter, "{} (user {})", self.name, id),
+            // Unlabelled API keys are already called "API key" by the registry.
+            ("api-key", _) if self.name != "API key" => {
+                write!(formatter, "{} (API key)", self.name)
+            }
+            _ => write!(formatter, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenDetails {
+    #[serde(default)]
+    scopes: Vec<String>,
+    #[serde(default)]
+    actions: Vec<String>,
+}
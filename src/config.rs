@@ -0,0 +1,125 @@
+//! Defines user-level configuration for gooey, which applies to every project
+//! on this machine.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The contents of `~/.gooey/config.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    #[serde(default)]
+    pub download: DownloadConfig,
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Self> {
+        let path = file_path()?;
+
+        let contents = match fs_err::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                if err.kind() == io::ErrorKind::NotFound {
+                    return Ok(Self::default());
+                } else {
+                    return Err(err.into());
+                }
+            }
+        };
+
+        let config = toml::from_str(&contents)
+            .with_context(|| format!("Malformed gooey config file at {}", path.display()))?;
+
+        Ok(config)
+    }
+}
+
+/// Settings that control how packages are downloaded from registries.
+///
+/// Example:
+/// ```toml
+/// [download]
+/// max-parallel-downloads = 8
+/// timeout = 60
+/// retries = 5
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct DownloadConfig {
+    /// The maximum number of packages that will be downloaded at once.
+    pub max_parallel_downloads: usize,
+
+    /// How long, in seconds, a single request to a registry may take before
+    /// it is abandoned.
+    pub timeout: u64,
+
+    /// How many times a request is retried after a transient error, like a
+    /// dropped connection or a 5xx response.
+    pub retries: u32,
+
+    /// How long, in milliseconds, to wait before the first retry. The wait is
+    /// doubled after each failed attempt.
+    pub retry_backoff: u64,
+}
+
+impl DownloadConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+
+    /// How long to wait before making the given retry attempt, starting at 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(self.retry_backoff.saturating_mul(factor))
+    }
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            max_parallel_downloads: 16,
+            timeout: 30,
+            retries: 3,
+            retry_backoff: 500,
+        }
+    }
+}
+
+fn file_path() -> anyhow::Result<PathBuf> {
+    let mut path = dirs::home_dir().context("Failed to find home directory")?;
+    path.push(".gooey");
+    path.push("config.toml");
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn partial_download_config() {
+        let config: Config = toml::from_str("[download]\nretries = 7\n").unwrap();
+
+        assert_eq!(config.download.retries, 7);
+        assert_eq!(
+            config.download.max_parallel_downloads,
+            DownloadConfig::default().max_parallel_downloads
+        );
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let config = DownloadConfig {
+            retry_backoff: 100,
+            ..Default::default()
+        };
+
+        assert_eq!(config.backoff(1), Duration::from_millis(100));
+        assert_eq!(config.backoff(2), Duration::from_millis(200));
+        assert_eq!(config.backoff(4), Duration::from_millis(800));
+    }
+}
@@ -5,28 +5,36 @@ use std::{
     time::Duration,
 };
 
-use anyhow::{bail, format_err};
+use anyhow::{bail, format_err, Context};
 use crossterm::style::{Color, SetForegroundColor};
 use fs_err as fs;
 use indicatif::{ProgressBar, ProgressStyle};
 use indoc::{formatdoc, indoc};
 
 use crate::{
-    manifest::Realm,
+    manifest::{InstallInfo, LinkMode, PlaceInfo, Realm},
     package_contents::PackageContents,
     package_id::PackageId,
     package_source::{PackageSourceMap, PackageSourceProvider},
+    package_types::{find_entry_module, read_type_exports, TypeExport},
     resolution::Resolve,
+    sourcemap::SourcemapNode,
 };
 
 #[derive(Clone)]
 pub struct InstallationContext {
+    project_path: PathBuf,
+    link_mode: LinkMode,
+    index_name: String,
     shared_dir: PathBuf,
     shared_index_dir: PathBuf,
     shared_path: Option<String>,
     server_dir: PathBuf,
     server_index_dir: PathBuf,
     server_path: Option<String>,
+    client_dir: PathBuf,
+    client_index_dir: PathBuf,
+    client_path: Option<String>,
     dev_dir: PathBuf,
     dev_index_dir: PathBuf,
 }
@@ -35,27 +43,37 @@ impl InstallationContext {
     /// Create a new `InstallationContext` for the given path.
     pub fn new(
         project_path: &Path,
-        shared_path: Option<String>,
-        server_path: Option<String>,
-    ) -> Self {
-        let shared_dir = project_path.join("Packages");
-        let server_dir = project_path.join("ServerPackages");
-        let dev_dir = project_path.join("DevPackages");
-
-        let shared_index_dir = shared_dir.join("_Index");
-        let server_index_dir = server_dir.join("_Index");
-        let dev_index_dir = dev_dir.join("_Index");
-
-        Self {
+        place: PlaceInfo,
+        install: InstallInfo,
+    ) -> anyhow::Result<Self> {
+        install.validate()?;
+
+        let shared_dir = project_path.join(&install.shared_dir);
+        let server_dir = project_path.join(&install.server_dir);
+        let client_dir = project_path.join(&install.client_dir);
+        let dev_dir = project_path.join(&install.dev_dir);
+
+        let shared_index_dir = shared_dir.join(&install.index_name);
+        let server_index_dir = server_dir.join(&install.index_name);
+        let client_index_dir = client_dir.join(&install.index_name);
+        let dev_index_dir = dev_dir.join(&install.index_name);
+
+        Ok(Self {
+            project_path: project_path.to_owned(),
+            link_mode: install.link_mode,
+            index_name: install.index_name,
             shared_dir,
             shared_index_dir,
-            shared_path,
+            shared_path: place.shared_packages,
             server_dir,
             server_index_dir,
-            server_path,
+            server_path: place.server_packages,
+            client_dir,
+            client_index_dir,
+            client_path: place.client_packages,
             dev_dir,
             dev_index_dir,
-        }
+        })
     }
 
     /// Delete the existing index, if it exists.
@@ -72,11 +90,53 @@ impl InstallationContext {
 
         remove_ignore_not_found(&self.shared_dir)?;
         remove_ignore_not_found(&self.server_dir)?;
+        remove_ignore_not_found(&self.client_dir)?;
         remove_ignore_not_found(&self.dev_dir)?;
 
         Ok(())
     }
 
+    /// Add the installed shared, server and client packages to the Rojo sourcemap at
+    /// `path`, creating it if it doesn't exist. Packages are placed at the
+    /// DataModel paths from the manifest's `[place]` section.
+    pub fn write_sourcemap(&self, root_name: &str, path: &Path) -> anyhow::Result<()> {
+        let mut sourcemap = SourcemapNode::load(path)?
+            .unwrap_or_else(|| SourcemapNode::new(root_name, "DataModel"));
+
+        let folders = [
+            (&self.shared_dir, &self.shared_path, "shared-packages"),
+            (&self.server_dir, &self.server_path, "server-packages"),
+            (&self.client_dir, &self.client_path, "client-packages"),
+        ];
+
+        for (dir, place_path, place_key) in folders.iter() {
+            if !dir.is_dir() {
+                continue;
+            }
+
+            let place_path = match place_path {
+                Some(place_path) => place_path,
+                None => {
+                    log::warn!(
+                        "Not adding {} to the sourcemap: `place.{}` is not set in gooey.toml",
+                        dir.display(),
+                        place_key
+                    );
+                    continue;
+                }
+            };
+
+            let folder_name = dir.file_name().unwrap().to_string_lossy();
+            let node = SourcemapNode::from_package_folder(&self.project_path, dir, &folder_name)?;
+            sourcemap.insert(place_path, node)?;
+        }
+
+        sourcemap.save(path)?;
+        log::info!("Wrote sourcemap to {}", path.display());
+
+        Ok(())
+    }
+
     /// Install all packages from the given `Resolve` into the package that this
     /// `InstallationContext` was built for.
     pub fn install(
@@ -86,8 +146,7 @@ impl InstallationContext {
         resolved: Resolve,
     ) -> anyhow::Result<()> {
         let mut handles = Vec::new();
-        let resolved_copy = resolved.clone();
-        let bar = ProgressBar::new((resolved_copy.activated.len() - 1) as u64).with_style(
+        let bar = ProgressBar::new((resolved.activated.len() - 1) as u64).with_style(
             ProgressStyle::with_template(
                 "{spinner:.cyan.bold} {pos}/{len} [{wide_bar:.cyan/blue}]",
             )
@@ -97,22 +156,88 @@ impl InstallationContext {
         );
         bar.enable_steady_tick(Duration::from_millis(100));
 
+        // Downloads are blocking tasks, so the size of the blocking pool is
+        // what bounds how many packages are downloaded at once.
         let runtime = tokio::runtime::Builder::new_multi_thread()
-            .worker_threads(50)
+            .worker_threads(1)
+            .max_blocking_threads(sources.download_config().max_parallel_downloads)
             .enable_all()
-            .build()
-            .unwrap();
+            .build()?;
+
+        for package_id in &resolved.activated {
+            // We do not need to install the root package.
+            if *package_id == root_package_id {
+                continue;
+            }
 
-        for package_id in resolved_copy.activated {
             log::debug!("Installing {}...", package_id);
 
-            let shared_deps = resolved.shared_dependencies.get(&package_id);
-            let server_deps = resolved.server_dependencies.get(&package_id);
-            let dev_deps = resolved.dev_dependencies.get(&package_id);
+            let metadata = &resolved.metadata[package_id];
+            let package_realm = metadata.origin_realm;
+            let source_registry = metadata.source_registry.clone();
+            let source_copy = sources.clone();
+            let context = self.clone();
+            let b = bar.clone();
+            let task_package_id = package_id.clone();
+
+            let handle = runtime.spawn_blocking(move || {
+                let package_id = task_package_id;
+                let package_source = source_copy.get(&source_registry).unwrap();
+                let contents = package_source.download_package(&package_id)?;
+                b.println(format!(
+                    "{} Downloaded {}{}",
+                    SetForegroundColor(Color::DarkGreen),
+                    SetForegroundColor(Color::Reset),
+                    package_id,
+                ));
+                b.inc(1);
+                context.write_contents(&package_id, &contents, package_realm)
+            });
+
+            handles.push((package_id.clone(), handle));
+        }
+
+        let num_packages = handles.len();
+        let mut failures = Vec::new();
 
-            // We do not need to install the root package, but we should create
-            // package links for its dependencies.
-            if package_id == root_package_id {
+        for (package_id, handle) in handles {
+            let result = match runtime.block_on(handle) {
+                Ok(result) => result,
+                Err(err) => Err(format_err!("installation task did not complete: {}", err)),
+            };
+
+            if let Err(err) = result {
+                failures.push((package_id, err));
+            }
+        }
+
+        bar.finish_and_clear();
+
+        if !failures.is_empty() {
+            let report: Vec<_> = failures
+                .iter()
+                .map(|(package_id, err)| format!("  {}: {:#}", package_id, err))
+                .collect();
+
+            bail!(
+                "Failed to install {} of {} packages:\n{}",
+                failures.len(),
+                num_packages,
+                report.join("\n")
+            );
+        }
+
+        // Links are written once every package is on disk, since they
+        // re-export the types declared by the packages they point to.
+        for package_id in &resolved.activated {
+            let shared_deps = resolved.shared_dependencies.get(package_id);
+            let server_deps = resolved.server_dependencies.get(package_id);
+            let client_deps = resolved.client_dependencies.get(package_id);
+            let dev_deps = resolved.dev_dependencies.get(package_id);
+
+            // The root package isn't installed, but we should create package
+            // links for its dependencies.
+            if *package_id == root_package_id {
                 if let Some(deps) = shared_deps {
                     self.write_root_package_links(Realm::Shared, deps, &resolved)?;
                 }
@@ -121,86 +246,86 @@ impl InstallationContext {
                     self.write_root_package_links(Realm::Server, deps, &resolved)?;
                 }
 
+                if let Some(deps) = client_deps {
+                    self.write_root_package_links(Realm::Client, deps, &resolved)?;
+                }
+
                 if let Some(deps) = dev_deps {
                     self.write_root_package_links(Realm::Dev, deps, &resolved)?;
                 }
             } else {
-                let metadata = resolved.metadata.get(&package_id).unwrap();
-                let package_realm = metadata.origin_realm;
+                let package_realm = resolved.metadata[package_id].origin_realm;
 
                 if let Some(deps) = shared_deps {
-                    self.write_package_links(&package_id, package_realm, deps, &resolved)?;
+                    self.write_package_links(package_id, package_realm, deps, &resolved)?;
                 }
 
                 if let Some(deps) = server_deps {
-                    self.write_package_links(&package_id, package_realm, deps, &resolved)?;
+                    self.write_package_links(package_id, package_realm, deps, &resolved)?;
                 }
 
-                if let Some(deps) = dev_deps {
-                    self.write_package_links(&package_id, package_realm, deps, &resolved)?;
+                if let Some(deps) = client_deps {
+                    self.write_package_links(package_id, package_realm, deps, &resolved)?;
                 }
 
-                let source_registry = resolved_copy.metadata[&package_id].source_registry.clone();
-                let source_copy = sources.clone();
-                let context = self.clone();
-                let b = bar.clone();
-
-                let handle = runtime.spawn_blocking(move || {
-                    let package_source = source_copy.get(&source_registry).unwrap();
-                    let contents = package_source.download_package(&package_id)?;
-                    b.println(format!(
-                        "{} Downloaded {}{}",
-                        SetForegroundColor(Color::DarkGreen),
-                        SetForegroundColor(Color::Reset),
-                        package_id,
-                    ));
-                    b.inc(1);
-                    context.write_contents(&package_id, &contents, package_realm)
-                });
-
-                handles.push(handle);
+                if let Some(deps) = dev_deps {
+                    self.write_package_links(package_id, package_realm, deps, &resolved)?;
+                }
             }
         }
 
-        let num_packages = handles.len();
-
-        for handle in handles {
-            runtime
-                .block_on(handle)
-                .expect("Package failed to be installed.")?;
+        if self.link_mode == LinkMode::Path {
+            self.write_luaurc()?;
         }
 
-        bar.finish_and_clear();
         log::info!("Downloaded {} packages!", num_packages);
 
         Ok(())
     }
 
+    /// Lua indexing the index folder from its parent instance, like `._Index`.
+    fn index_access(&self) -> String {
+        let is_identifier = !self.index_name.starts_with(|c: char| c.is_ascii_digit())
+            && self
+                .index_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        if is_identifier {
+            format!(".{}", self.index_name)
+        } else {
+            format!(r#"["{}"]"#, self.index_name)
+        }
+    }
+
     /// Contents of a package-to-package link within the same index.
-    fn link_sibling_same_index(&self, id: &PackageId) -> String {
-        formatdoc! {r#"
-            return require(script.Parent.Parent["{full_name}"]["{short_name}"])
-            "#,
+    fn link_sibling_same_index(&self, id: &PackageId, types: &[TypeExport]) -> String {
+        let target = format!(
+            r#"script.Parent.Parent["{full_name}"]["{short_name}"]"#,
             full_name = package_id_file_name(id),
             short_name = id.name().name()
-        }
+        );
+
+        link_contents(&target, types)
     }
 
     /// Contents of a root-to-package link within the same index.
-    fn link_root_same_index(&self, id: &PackageId) -> String {
-        formatdoc! {r#"
-            return require(script.Parent._Index["{full_name}"]["{short_name}"])
-            "#,
+    fn link_root_same_index(&self, id: &PackageId, types: &[TypeExport]) -> String {
+        let target = format!(
+            r#"script.Parent{index}["{full_name}"]["{short_name}"]"#,
+            index = self.index_access(),
             full_name = package_id_file_name(id),
             short_name = id.name().name()
-        }
+        );
+
+        link_contents(&target, types)
     }
 
     /// Contents of a link into the shared index from outside the shared index.
-    fn link_shared_index(&self, id: &PackageId) -> anyhow::Result<String> {
+    fn link_shared_index(&self, id: &PackageId, types: &[TypeExport]) -> anyhow::Result<String> {
         let shared_path = self.shared_path.as_ref().ok_or_else(|| {
             format_err!(indoc! {r#"
-                A server or dev dependency is depending on a shared dependency.
+                A server, client or dev dependency is depending on a shared dependency.
                 To link these packages correctly you must declare where shared
                 packages are placed in the roblox datamodel in your gooey.toml.
                 
@@ -211,19 +336,19 @@ impl InstallationContext {
             "#})
         })?;
 
-        let contents = formatdoc! {r#"
-            return require({packages}._Index["{full_name}"]["{short_name}"])
-            "#,
+        let target = format!(
+            r#"{packages}{index}["{full_name}"]["{short_name}"]"#,
             packages = shared_path,
+            index = self.index_access(),
             full_name = package_id_file_name(id),
             short_name = id.name().name()
-        };
+        );
 
-        Ok(contents)
+        Ok(link_contents(&target, types))
     }
 
     /// Contents of a link into the server index from outside the server index.
-    fn link_server_index(&self, id: &PackageId) -> anyhow::Result<String> {
+    fn link_server_index(&self, id: &PackageId, types: &[TypeExport]) -> anyhow::Result<String> {
         let server_path = self.server_path.as_ref().ok_or_else(|| {
             format_err!(indoc! {r#"
                 A dev dependency is depending on a server dependency.
@@ -237,15 +362,110 @@ impl InstallationContext {
             "#})
         })?;
 
-        let contents = formatdoc! {r#"
-            return require({packages}._Index["{full_name}"]["{short_name}"])
-            "#,
+        let target = format!(
+            r#"{packages}{index}["{full_name}"]["{short_name}"]"#,
             packages = server_path,
+            index = self.index_access(),
             full_name = package_id_file_name(id),
             short_name = id.name().name()
+        );
+
+        Ok(link_contents(&target, types))
+    }
+
+    /// Contents of a link that requires a package by its path relative to the
+    /// directory the link is written to.
+    fn link_relative(
+        &self,
+        link_dir: &Path,
+        id: &PackageId,
+        realm: Realm,
+        types: &[TypeExport],
+    ) -> String {
+        let package_dir = self.package_dir(id, realm);
+
+        // String requires resolve a directory to its init module and a file
+        // by its name without an extension.
+        let entry = match find_entry_module(&package_dir) {
+            Some(entry) if entry.file_stem().map_or(false, |stem| stem == "init") => {
+                entry.parent().unwrap().to_owned()
+            }
+            Some(entry) => entry.with_extension(""),
+            None => package_dir,
+        };
+
+        let target = format!("\"{}\"", relative_require_path(link_dir, &entry));
+        link_contents(&target, types)
+    }
+
+    /// Add aliases for each package folder to the project's `.luaurc`, so that
+    /// packages can be required like `require("@Packages/Foo")`.
+    fn write_luaurc(&self) -> anyhow::Result<()> {
+        let path = self.project_path.join(".luaurc");
+
+        let mut luaurc = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Malformed .luaurc at {}", path.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => serde_json::json!({}),
+            Err(err) => return Err(err.into()),
         };
 
-        Ok(contents)
+        let aliases = luaurc
+            .as_object_mut()
+            .ok_or_else(|| format_err!("{} must contain a JSON object", path.display()))?
+            .entry("aliases")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+            .ok_or_else(|| format_err!("`aliases` in {} must be an object", path.display()))?;
+
+        let dirs = [
+            &self.shared_dir,
+            &self.server_dir,
+            &self.client_dir,
+            &self.dev_dir,
+        ];
+
+        for dir in dirs.iter() {
+            if dir.is_dir() {
+                let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+                let target = relative_require_path(&self.project_path, dir);
+                aliases.insert(name, target.into());
+            }
+        }
+
+        let mut contents = serde_json::to_string_pretty(&luaurc)?;
+        contents.push('\n');
+
+        log::trace!("Writing {}", path.display());
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    /// Contents of a link into the client index from outside the client index.
+    fn link_client_index(&self, id: &PackageId, types: &[TypeExport]) -> anyhow::Result<String> {
+        let client_path = self.client_path.as_ref().ok_or_else(|| {
+            format_err!(indoc! {r#"
+                A dev dependency is depending on a client dependency.
+                To link these packages correctly you must declare where client
+                packages are placed in the roblox datamodel in your gooey.toml.
+                
+                This typically looks like:
+
+                [place]
+                client-packages = "game.StarterPlayer.StarterPlayerScripts.Packages"
+            "#})
+        })?;
+
+        let target = format!(
+            r#"{packages}{index}["{full_name}"]["{short_name}"]"#,
+            packages = client_path,
+            index = self.index_access(),
+            full_name = package_id_file_name(id),
+            short_name = id.name().name()
+        );
+
+        Ok(link_contents(&target, types))
     }
 
     fn write_root_package_links<'a, K: Display>(
@@ -259,6 +479,7 @@ impl InstallationContext {
         let base_path = match root_realm {
             Realm::Shared => &self.shared_dir,
             Realm::Server => &self.server_dir,
+            Realm::Client => &self.client_dir,
             Realm::Dev => &self.dev_dir,
         };
 
@@ -268,11 +489,21 @@ impl InstallationContext {
         for (dep_name, dep_package_id) in dependencies {
             let dependencies_realm = resolved.metadata.get(dep_package_id).unwrap().origin_realm;
             let path = base_path.join(format!("{}.lua", dep_name));
+            let types = read_type_exports(&self.package_dir(dep_package_id, dependencies_realm));
 
             let contents = match (root_realm, dependencies_realm) {
-                (source, dest) if source == dest => self.link_root_same_index(dep_package_id),
-                (_, Realm::Server) => self.link_server_index(dep_package_id)?,
-                (_, Realm::Shared) => self.link_shared_index(dep_package_id)?,
+                (source, dest)
+                    if self.link_mode == LinkMode::Path
+                        && (source == dest || dest != Realm::Dev) =>
+                {
+                    self.link_relative(base_path, dep_package_id, dependencies_realm, &types)
+                }
+                (source, dest) if source == dest => {
+                    self.link_root_same_index(dep_package_id, &types)
+                }
+                (_, Realm::Server) => self.link_server_index(dep_package_id, &types)?,
+                (_, Realm::Client) => self.link_client_index(dep_package_id, &types)?,
+                (_, Realm::Shared) => self.link_shared_index(dep_package_id, &types)?,
                 (_, Realm::Dev) => {
                     bail!("A dev dependency cannot be depended upon by a non-dev dependency")
                 }
@@ -297,6 +528,7 @@ impl InstallationContext {
         let mut base_path = match package_realm {
             Realm::Shared => self.shared_index_dir.clone(),
             Realm::Server => self.server_index_dir.clone(),
+            Realm::Client => self.client_index_dir.clone(),
             Realm::Dev => self.dev_index_dir.clone(),
         };
 
@@ -308,11 +540,21 @@ impl InstallationContext {
         for (dep_name, dep_package_id) in dependencies {
             let dependencies_realm = resolved.metadata.get(dep_package_id).unwrap().origin_realm;
             let path = base_path.join(format!("{}.lua", dep_name));
+            let types = read_type_exports(&self.package_dir(dep_package_id, dependencies_realm));
 
             let contents = match (package_realm, dependencies_realm) {
-                (source, dest) if source == dest => self.link_sibling_same_index(dep_package_id),
-                (_, Realm::Server) => self.link_server_index(dep_package_id)?,
-                (_, Realm::Shared) => self.link_shared_index(dep_package_id)?,
+                (source, dest)
+                    if self.link_mode == LinkMode::Path
+                        && (source == dest || dest != Realm::Dev) =>
+                {
+                    self.link_relative(&base_path, dep_package_id, dependencies_realm, &types)
+                }
+                (source, dest) if source == dest => {
+                    self.link_sibling_same_index(dep_package_id, &types)
+                }
+                (_, Realm::Server) => self.link_server_index(dep_package_id, &types)?,
+                (_, Realm::Client) => self.link_client_index(dep_package_id, &types)?,
+                (_, Realm::Shared) => self.link_shared_index(dep_package_id, &types)?,
                 (_, Realm::Dev) => {
                     bail!("A dev dependency cannot be depended upon by a non-dev dependency")
                 }
@@ -331,20 +573,70 @@ impl InstallationContext {
         contents: &PackageContents,
         realm: Realm,
     ) -> anyhow::Result<()> {
+        let path = self.package_dir(package_id, realm);
+
+        fs::create_dir_all(&path)?;
+        contents.unpack_into_path(&path)?;
+
+        Ok(())
+    }
+
+    /// The directory that the contents of a package are unpacked into.
+    fn package_dir(&self, package_id: &PackageId, realm: Realm) -> PathBuf {
         let mut path = match realm {
             Realm::Shared => self.shared_index_dir.clone(),
             Realm::Server => self.server_index_dir.clone(),
+            Realm::Client => self.client_index_dir.clone(),
             Realm::Dev => self.dev_index_dir.clone(),
         };
 
         path.push(package_id_file_name(package_id));
         path.push(package_id.name().name());
+        path
+    }
+}
 
-        fs::create_dir_all(&path)?;
-        contents.unpack_into_path(&path)?;
+/// Creates the contents of a link file that requires `target`. Any types the
+/// linked package exports are re-exported so they survive the link.
+fn link_contents(target: &str, types: &[TypeExport]) -> String {
+    if types.is_empty() {
+        return formatdoc! {r#"
+            return require({target})
+            "#,
+            target = target
+        };
+    }
 
-        Ok(())
+    let mut contents = format!("local REQUIRED_MODULE = require({})\n", target);
+
+    for export in types {
+        contents.push_str(&export.reexport("REQUIRED_MODULE"));
+        contents.push('\n');
+    }
+
+    contents.push_str("return REQUIRED_MODULE\n");
+    contents
+}
+
+/// Creates a path for a string require from a module in `from_dir` to `to`,
+/// like `./_Index/scope_name@1.0.0/name` or `../../Packages/Foo`.
+fn relative_require_path(from_dir: &Path, to: &Path) -> String {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts: Vec<String> = vec!["..".to_owned(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+    );
+
+    if parts.first().map_or(true, |part| part != "..") {
+        parts.insert(0, ".".to_owned());
     }
+
+    parts.join("/")
 }
 
 /// Creates a suitable name for use in file paths that refer to this package.
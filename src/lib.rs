@@ -1,5 +1,7 @@
+pub mod api_error;
 pub mod auth;
 pub mod commands;
+pub mod config;
 pub mod git_util;
 pub mod installation;
 pub mod lockfile;
@@ -10,8 +12,12 @@ pub mod package_index;
 pub mod package_name;
 pub mod package_req;
 pub mod package_source;
+pub mod package_types;
+pub mod package_validation;
 pub mod resolution;
+pub mod sourcemap;
 pub mod test_package;
+pub mod workspace;
 pub mod package_compat;
 
 pub use commands::*;
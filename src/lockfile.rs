@@ -57,6 +57,7 @@ impl Lockfile {
             let dependencies = [
                 grab_dependencies(&package_id, &resolve.shared_dependencies),
                 grab_dependencies(&package_id, &resolve.server_dependencies),
+                grab_dependencies(&package_id, &resolve.client_dependencies),
                 grab_dependencies(&package_id, &resolve.dev_dependencies),
             ]
             .concat();
@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::path::{Component, Path};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
@@ -28,12 +28,18 @@ pub struct Manifest {
     #[serde(default)]
     pub place: PlaceInfo,
 
+    #[serde(default)]
+    pub install: InstallInfo,
+
     #[serde(default)]
     pub dependencies: BTreeMap<String, PackageReq>,
 
     #[serde(default)]
     pub server_dependencies: BTreeMap<String, PackageReq>,
 
+    #[serde(default)]
+    pub client_dependencies: BTreeMap<String, PackageReq>,
+
     #[serde(default)]
     pub dev_dependencies: BTreeMap<String, PackageReq>,
 }
@@ -88,10 +94,10 @@ pub struct Package {
     /// The realms (`shared`, `server`, etc) that this package can be used in.
     ///
     /// Packages in the `shared` realm can only depend on other `shared`
-    /// packages. Packages in the `server` realm can depend on any other
-    /// package.
+    /// packages. Packages in the `server` and `client` realms can depend on
+    /// `shared` packages and packages of their own realm.
     ///
-    /// Example: `shared`, `server`
+    /// Example: `shared`, `server`, `client`
     pub realm: Realm,
 
     /// A short description of the package.
@@ -110,6 +116,19 @@ pub struct Package {
     #[serde(default)]
     pub authors: Vec<String>,
 
+    /// Words that people might search for to find the package.
+    ///
+    /// Example: ["promise", "async"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+
+    /// Categories the package belongs to, which registries can use to group
+    /// packages together.
+    ///
+    /// Example: ["networking", "utilities"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
     /// A list of paths to include in the package. Glob patterns are supported.
     ///
     /// By default all directories and files are included except files generated
@@ -117,7 +136,8 @@ pub struct Package {
     /// files matching patterns in the include list will be included.
     ///
     /// If include is unspecified and a .gitignore file exists then those patterns
-    /// will be respected and gooey will also ignore those files.
+    /// will be respected and gooey will also ignore those files. Patterns in a
+    /// .gooeyignore file are always respected.
     ///
     /// Example: ["/src", "*.lua"]
     #[serde(default)]
@@ -129,7 +149,7 @@ pub struct Package {
     /// excluded. If a .gitignore file exists and include is unspecified then
     /// those patterns will be respected and gooey will also ignore those files.
     /// Patterns in exclude will be excluded in addition to those patterns in the
-    /// .gitignore.
+    /// .gitignore and .gooeyignore files.
     ///
     /// Example: ["/Packages", "/node_modules"]
     #[serde(default)]
@@ -158,6 +178,12 @@ pub struct PlaceInfo {
     /// Example: `game.ServerScriptStorage.Packages`
     #[serde(default)]
     pub server_packages: Option<String>,
+
+    /// Where the client packages folder is located in the Roblox Datamodel
+    ///
+    /// Example: `game.StarterPlayer.StarterPlayerScripts.Packages`
+    #[serde(default)]
+    pub client_packages: Option<String>,
 }
 
 impl Default for PlaceInfo {
@@ -165,15 +191,185 @@ impl Default for PlaceInfo {
         Self {
             shared_packages: None,
             server_packages: None,
+            client_packages: None,
+        }
+    }
+}
+
+/// Settings for how packages are laid out when they are installed. Like
+/// `PlaceInfo`, this is only used in the root package.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InstallInfo {
+    /// How link files require the packages they point to.
+    ///
+    /// Example: `path`
+    #[serde(default)]
+    pub link_mode: LinkMode,
+
+    /// Where shared packages are installed, relative to the project.
+    ///
+    /// Example: `src/Vendor`
+    #[serde(default = "default_shared_dir")]
+    pub shared_dir: String,
+
+    /// Where server packages are installed, relative to the project.
+    ///
+    /// Example: `ServerPackages`
+    #[serde(default = "default_server_dir")]
+    pub server_dir: String,
+
+    /// Where client packages are installed, relative to the project.
+    ///
+    /// Example: `ClientPackages`
+    #[serde(default = "default_client_dir")]
+    pub client_dir: String,
+
+    /// Where dev packages are installed, relative to the project.
+    ///
+    /// Example: `DevPackages`
+    #[serde(default = "default_dev_dir")]
+    pub dev_dir: String,
+
+    /// The name of the folder within each package directory that holds the
+    /// contents of every installed package.
+    ///
+    /// Example: `_Index`
+    #[serde(default = "default_index_name")]
+    pub index_name: String,
+}
+
+impl InstallInfo {
+    /// The package directories for every realm, which are managed by gooey
+    /// and never included in a published package.
+    pub fn package_dirs(&self) -> [&str; 4] {
+        [
+            self.shared_dir.as_str(),
+            self.server_dir.as_str(),
+            self.client_dir.as_str(),
+            self.dev_dir.as_str(),
+        ]
+    }
+
+    /// Check that the configured directories can be safely cleaned and
+    /// reinstalled into.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let dirs = self.package_dirs();
+
+        for dir in dirs.iter() {
+            let path = Path::new(dir);
+            let is_plain_relative = path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+
+            if dir.is_empty() || !is_plain_relative {
+                bail!(
+                    "Package directory `{}` must be a relative path inside the project",
+                    dir
+                );
+            }
+        }
+
+        for (i, dir) in dirs.iter().enumerate() {
+            for other in &dirs[i + 1..] {
+                if Path::new(dir).starts_with(other) || Path::new(other).starts_with(dir) {
+                    bail!(
+                        "Package directories `{}` and `{}` must not overlap",
+                        dir,
+                        other
+                    );
+                }
+            }
+        }
+
+        let index_is_plain = Path::new(&self.index_name)
+            .components()
+            .map(|component| matches!(component, Component::Normal(_)))
+            .eq(std::iter::once(true));
+
+        if !index_is_plain {
+            bail!(
+                "Index folder name `{}` must be a single folder name",
+                self.index_name
+            );
+        }
+
+        // The name is written into link modules as a Luau string.
+        let index_is_quotable = !self
+            .index_name
+            .chars()
+            .any(|c| c == '"' || c == '\\' || c.is_control());
+
+        if !index_is_quotable {
+            bail!(
+                "Index folder name `{}` must not contain quotes, backslashes or control characters",
+                self.index_name
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for InstallInfo {
+    fn default() -> Self {
+        Self {
+            link_mode: LinkMode::default(),
+            shared_dir: default_shared_dir(),
+            server_dir: default_server_dir(),
+            client_dir: default_client_dir(),
+            dev_dir: default_dev_dir(),
+            index_name: default_index_name(),
         }
     }
 }
 
+fn default_shared_dir() -> String {
+    "Packages".to_owned()
+}
+
+fn default_server_dir() -> String {
+    "ServerPackages".to_owned()
+}
+
+fn default_client_dir() -> String {
+    "ClientPackages".to_owned()
+}
+
+fn default_dev_dir() -> String {
+    "DevPackages".to_owned()
+}
+
+fn default_index_name() -> String {
+    "_Index".to_owned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Links require packages through Roblox instances, like
+    /// `require(script.Parent._Index["scope_name@1.0.0"]["name"])`.
+    Roblox,
+
+    /// Links require packages by relative string paths, like
+    /// `require("./_Index/scope_name@1.0.0/name")`, for runtimes that load
+    /// modules from the filesystem such as Lune or the Luau CLI. A `.luaurc`
+    /// with aliases for each package folder is generated alongside them.
+    Path,
+}
+
+impl Default for LinkMode {
+    fn default() -> Self {
+        LinkMode::Roblox
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Realm {
     Server,
     Shared,
+    Client,
     Dev,
 }
 
@@ -183,7 +379,84 @@ impl Realm {
 
         matches!(
             (dep_type, dep_realm),
-            (Server, _) | (Shared, Shared) | (Dev, _)
+            (Server, Server)
+                | (Server, Shared)
+                | (Server, Dev)
+                | (Shared, Shared)
+                | (Client, Client)
+                | (Client, Shared)
+                | (Dev, _)
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn install_info(dirs: [&str; 3]) -> InstallInfo {
+        InstallInfo {
+            shared_dir: dirs[0].to_owned(),
+            server_dir: dirs[1].to_owned(),
+            dev_dir: dirs[2].to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_package_dirs() {
+        InstallInfo::default().validate().unwrap();
+        install_info(["src/Vendor", "src/ServerVendor", "DevPackages"])
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn invalid_package_dirs() {
+        assert!(install_info(["", "ServerPackages", "DevPackages"])
+            .validate()
+            .is_err());
+        assert!(install_info([".", "ServerPackages", "DevPackages"])
+            .validate()
+            .is_err());
+        assert!(
+            install_info(["../Packages", "ServerPackages", "DevPackages"])
+                .validate()
+                .is_err()
+        );
+        assert!(install_info(["/Packages", "ServerPackages", "DevPackages"])
+            .validate()
+            .is_err());
+        assert!(install_info(["Packages", "Packages/Server", "DevPackages"])
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn client_dependencies() {
+        assert!(Realm::is_dependency_valid(Realm::Client, Realm::Shared));
+        assert!(Realm::is_dependency_valid(Realm::Client, Realm::Client));
+        assert!(!Realm::is_dependency_valid(Realm::Client, Realm::Server));
+        assert!(!Realm::is_dependency_valid(Realm::Server, Realm::Client));
+        assert!(!Realm::is_dependency_valid(Realm::Shared, Realm::Client));
+    }
+
+    #[test]
+    fn invalid_index_name() {
+        let install = InstallInfo {
+            index_name: "a/b".to_owned(),
+            ..Default::default()
+        };
+
+        assert!(install.validate().is_err());
+
+        for index_name in &["a\"b", "a\\b", "a\nb"] {
+            let install = InstallInfo {
+                index_name: (*index_name).to_owned(),
+                ..Default::default()
+            };
+
+            assert!(install.validate().is_err(), "{:?}", index_name);
+        }
+    }
+}
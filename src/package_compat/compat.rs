@@ -31,7 +31,7 @@ const PACKAGE_COMPATIBILITY_LAYERS: [PackageCompatibilityLayer; 3] = [
     },
 ];
 
-fn handle_based_on_file_path(path: &Path) -> anyhow::Result<Option<String>> {
+pub fn handle_based_on_file_path(path: &Path) -> anyhow::Result<Option<String>> {
     let mut best_manifest_name : Option<String> = None;
     let mut best_layer_entry : Option<DirEntry> = None;
     let mut best_layer_weight = 99;
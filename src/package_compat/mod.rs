@@ -7,4 +7,4 @@ mod wally_compat;
 
 use wally_manifest::*;
 
-pub use compat::load_backwards_compatible_package;
\ No newline at end of file
+pub use compat::{handle_based_on_file_path, load_backwards_compatible_package};
\ No newline at end of file
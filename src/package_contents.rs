@@ -1,20 +1,23 @@
-use std::io::{self, BufRead, BufReader, Cursor};
+use std::io::{self, BufReader, Cursor};
 use std::path::{Path, PathBuf};
 
 use anyhow::format_err;
 use fs_err::File;
 use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
 use serde_json::json;
-use walkdir::WalkDir;
-use zip::{write::FileOptions, ZipArchive, ZipWriter};
+use zip::{write::FileOptions, CompressionMethod, DateTime, ZipArchive, ZipWriter};
 use crate::package_compat;
+use crate::package_validation::{validate_archive, validate_package};
+
+const FILE_PERMISSIONS: u32 = 0o644;
+const DIRECTORY_PERMISSIONS: u32 = 0o755;
+
+pub const GOOEYIGNORE_FILE_NAME: &str = ".gooeyignore";
 
 static EXCLUDED_GLOBS: &[&str] = &[
     ".*",
     "gooey.lock",
-    "Packages",
-    "ServerPackages",
-    "DevPackages",
 ];
 
 /// Container for the contents of a package that have been downloaded.
@@ -29,6 +32,8 @@ impl PackageContents {
         let manifest = package_compat::load_backwards_compatible_package(input)?;
         let package_name = manifest.package.name.name();
 
+        validate_package(input, &manifest)?;
+
         let mut data = Vec::new();
         let mut archive = ZipWriter::new(Cursor::new(&mut data));
 
@@ -47,18 +52,18 @@ impl PackageContents {
             let archive_name = str::replace(archive_name, "\\", "/");
 
             if path.is_dir() {
-                archive.add_directory(archive_name, FileOptions::default())?;
+                archive.add_directory(archive_name, archive_options(DIRECTORY_PERMISSIONS))?;
             } else {
-                archive.start_file(archive_name, FileOptions::default())?;
+                archive.start_file(archive_name, archive_options(FILE_PERMISSIONS))?;
 
                 if path.ends_with("default.project.json") {
-                    let project_file = File::open(path)?;
+                    let project_file = File::open(&path)?;
                     let mut project_json: serde_json::Value =
                         serde_json::from_reader(project_file)?;
                     let project_name = project_json
                         .get("name")
                         .and_then(|name| name.as_str())
-                        .expect("Couldn't parse name in default.project.json");
+                        .ok_or_else(|| format_err!("{} must have a `name`", path.display()))?;
 
                     if project_name != package_name {
                         log::info!(
@@ -82,7 +87,10 @@ impl PackageContents {
         archive.finish()?;
         drop(archive);
 
-        Ok(PackageContents { data })
+        let contents = PackageContents { data };
+        validate_archive(&contents)?;
+
+        Ok(contents)
     }
 
     /// Unpack the package into the given path on the filesystem.
@@ -92,57 +100,101 @@ impl PackageContents {
         Ok(())
     }
 
+    /// List every file and directory in the project at `input` that belongs in
+    /// its package, in a stable order.
+    ///
+    /// Paths are filtered by the manifest's `include` and `exclude` lists, any
+    /// `.gooeyignore` files, and, when `include` is empty, any `.gitignore`
+    /// files. Ignore files use gitignore semantics and are read from `input`
+    /// and its subdirectories only.
     pub fn filtered_contents(input: &Path) -> anyhow::Result<Vec<PathBuf>> {
         let manifest = package_compat::load_backwards_compatible_package(input)?;
         let includes = manifest.package.include;
         let mut excludes = manifest.package.exclude;
 
-        if includes.is_empty() && Path::new(".gitignore").exists() {
-            let gitignore = File::open(Path::new(".gitignore"))?;
-
-            BufReader::new(gitignore)
-                .lines()
-                .flatten()
-                .for_each(|pattern| {
-                    excludes.push(pattern);
-                });
-        }
-
         EXCLUDED_GLOBS
             .iter()
+            .copied()
+            .chain(manifest.install.package_dirs().iter().copied())
             .map(|pattern| pattern.to_string())
             .for_each(|pattern| excludes.push(pattern));
 
         let include = build_glob_set(&includes)?;
         let exclude = build_glob_set(&excludes)?;
+        let root = input.to_owned();
+        let has_includes = !includes.is_empty();
+
+        let mut walker = WalkBuilder::new(input);
+        walker
+            .standard_filters(false)
+            .hidden(true)
+            .git_ignore(!has_includes)
+            .require_git(false)
+            .add_custom_ignore_filename(GOOEYIGNORE_FILE_NAME)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                let relative = match entry.path().strip_prefix(&root) {
+                    Ok(relative) => relative,
+                    Err(_) => return true,
+                };
 
-        Ok(WalkDir::new(input)
-            .min_depth(1)
-            .into_iter()
-            .filter_entry(|entry| {
-                let relative = entry.path().strip_prefix(input).unwrap();
-
-                if !includes.is_empty() && !include.matches(relative).is_empty() {
+                if has_includes && !include.matches(relative).is_empty() {
                     return true;
                 };
 
                 exclude.matches(relative).is_empty()
-            })
-            .flatten()
-            .map(|entry| entry.path().to_path_buf())
-            .collect())
+            });
+
+        let mut paths = Vec::new();
+
+        for entry in walker.build() {
+            let entry = entry?;
+
+            if entry.depth() > 0 {
+                paths.push(entry.into_path());
+            }
+        }
+
+        Ok(paths)
     }
 
     pub fn data(&self) -> &[u8] {
         &self.data
     }
 
+    /// List the files in the package along with their uncompressed sizes, in
+    /// the order they appear in the archive.
+    pub fn files(&self) -> anyhow::Result<Vec<(String, u64)>> {
+        let mut archive = ZipArchive::new(Cursor::new(self.data.as_slice()))?;
+        let mut files = Vec::new();
+
+        for i in 0..archive.len() {
+            let file = archive.by_index(i)?;
+
+            if file.is_file() {
+                files.push((file.name().to_owned(), file.size()));
+            }
+        }
+
+        Ok(files)
+    }
+
     /// Create a new PackageContents object from a buffer.
     pub fn from_buffer(data: Vec<u8>) -> PackageContents {
         PackageContents { data }
     }
 }
 
+/// Options for an entry in a package archive. Anything that depends on the
+/// machine doing the packing, like timestamps and permissions, is fixed so
+/// that packing the same files always produces the same bytes.
+fn archive_options(permissions: u32) -> FileOptions {
+    FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(DateTime::default())
+        .unix_permissions(permissions)
+}
+
 fn build_glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
     let mut builder = GlobSetBuilder::new();
 
@@ -1,21 +1,26 @@
-use std::collections::HashMap;
-use std::io::{BufReader, ErrorKind, Write};
-use std::path::PathBuf;
+mod index_write;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Context};
-use fs_err::{create_dir_all, File, OpenOptions};
+use fs_err::File;
 use git2::Repository;
 use serde::{Deserialize, Serialize};
 use tempfile::TempDir;
 use url::Url;
 
-use crate::git_util;
+use crate::git_util::{self, GitCredentials};
 use crate::manifest::Manifest;
 use crate::package_name::PackageName;
 
+use self::index_write::{IndexChange, PendingWrite};
+
 /// Configuration contained in the index's `config.json` file.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageIndexConfig {
     pub api: Url,
     pub github_oauth_id: Option<String>,
@@ -35,13 +40,20 @@ pub struct PackageIndex {
     /// index repository, like updating or clearing it.
     repository: Mutex<Repository>,
 
-    /// A cache that contains all of the packages we've queried so far. This
-    /// cache is never emptied.
+    /// A cache that contains all of the packages we've queried so far. Entries
+    /// are only removed when the index is changed through this handle.
     package_cache: Mutex<HashMap<PackageName, Arc<PackageMetadata>>>,
 
-    /// A GitHub Personal Access Token to use before trying the machine's local
-    /// configuration.
-    access_token: Option<String>,
+    /// Changes waiting to be committed to the index.
+    pending_writes: Mutex<Vec<PendingWrite>>,
+
+    /// Credentials to use before trying the machine's local configuration.
+    credentials: GitCredentials,
+
+    /// Whether this index is its own source of truth, with no remote to update
+    /// from or push changes to. Changes are still committed, so the history of
+    /// the index is kept.
+    local: bool,
 
     /// If this index is contained in a temporary location, like when running
     /// tests or a registry server, hold onto it here so that it'll be dropped
@@ -51,16 +63,18 @@ pub struct PackageIndex {
 }
 
 impl PackageIndex {
-    pub fn new(index_url: &Url, access_token: Option<String>) -> anyhow::Result<Self> {
+    pub fn new(index_url: &Url, credentials: GitCredentials) -> anyhow::Result<Self> {
         let path = index_path(index_url)?;
-        let repository = git_util::open_or_clone(access_token.clone(), index_url, &path)?;
+        let repository = git_util::open_or_clone(credentials.clone(), index_url, &path)?;
 
         let index = Self {
             url: index_url.clone(),
             path,
             repository: Mutex::new(repository),
             package_cache: Mutex::new(HashMap::new()),
-            access_token,
+            pending_writes: Mutex::new(Vec::new()),
+            credentials,
+            local: false,
             temp_dir: None,
         };
 
@@ -76,17 +90,19 @@ impl PackageIndex {
         &self.path
     }
 
-    pub fn new_temp(index_url: &Url, access_token: Option<String>) -> anyhow::Result<Self> {
+    pub fn new_temp(index_url: &Url, credentials: GitCredentials) -> anyhow::Result<Self> {
         let temp_dir = tempfile::tempdir()?;
         let path = temp_dir.path().to_owned();
-        let repository = git_util::open_or_clone(access_token.clone(), index_url, &path)?;
+        let repository = git_util::open_or_clone(credentials.clone(), index_url, &path)?;
 
         let index = Self {
             url: index_url.clone(),
             path,
             repository: Mutex::new(repository),
             package_cache: Mutex::new(HashMap::new()),
-            access_token,
+            pending_writes: Mutex::new(Vec::new()),
+            credentials,
+            local: false,
             temp_dir: Some(temp_dir),
         };
 
@@ -94,14 +110,39 @@ impl PackageIndex {
         Ok(index)
     }
 
+    /// Open an index kept in a local directory that isn't cloned from
+    /// anywhere, creating it if it doesn't exist yet. This is meant for
+    /// registry servers that own their index.
+    pub fn new_local(path: &Path) -> anyhow::Result<Self> {
+        let repository = git_util::open_or_init(path)?;
+        let path = fs_err::canonicalize(path)?;
+        let url = Url::from_directory_path(&path)
+            .map_err(|_| anyhow!("invalid index path {}", path.display()))?;
+
+        Ok(Self {
+            url,
+            path,
+            repository: Mutex::new(repository),
+            package_cache: Mutex::new(HashMap::new()),
+            pending_writes: Mutex::new(Vec::new()),
+            credentials: GitCredentials::default(),
+            local: true,
+            temp_dir: None,
+        })
+    }
+
     pub fn update(&self) -> anyhow::Result<()> {
+        if self.local {
+            return Ok(());
+        }
+
         let repository = self.repository.lock().unwrap();
 
         log::info!(
             "Updating package index {}...",
             repository.find_remote("origin")?.url().unwrap()
         );
-        git_util::update_index(self.access_token.clone(), &repository)
+        git_util::update_index(self.credentials.clone(), &repository)
             .with_context(|| format!("could not update package index"))?;
 
         Ok(())
@@ -113,46 +154,29 @@ impl PackageIndex {
         Ok(serde_json::from_str(&contents)?)
     }
 
+    /// Replace the index's `config.json`. Like publishing, this changes our
+    /// local copy and then attempts to push it to the remote index.
+    pub fn set_config(&self, config: &PackageIndexConfig) -> anyhow::Result<()> {
+        self.write(IndexChange::SetConfig(config.clone()))
+    }
+
     /// Publish a package to the local copy of the index and attempt to push it
-    /// to the remote index, allowing a certain number of retries.
+    /// to the remote index. If the remote index has changed in the meantime,
+    /// the package is published on top of its latest version instead, retrying
+    /// a few times.
     ///
     /// Note that this method does not interact with any remote registry
     /// servers; it's intended for use with local registries or in the
     /// implementation of the registry server itself.
     pub fn publish(&self, manifest: &Manifest) -> anyhow::Result<()> {
-        let repo = self.repository.lock().unwrap();
-        let package_path = self.package_path(&manifest.package.name);
-
-        // This package might not exist yet, so create its containing directory.
-        create_dir_all(package_path.parent().unwrap())?;
-
-        {
-            let mut file = OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(&package_path)?;
-
-            // Package entries are newline-delimited JSON files. We assume here
-            // that the file is empty or already ends in a newline.
-            let mut entry = serde_json::to_string(&manifest)?;
-            entry.push('\n');
-            file.write_all(entry.as_bytes())?;
-        }
-
-        git_util::commit_and_push(
-            &repo,
-            self.access_token.clone(),
-            &format!("Publish {}", manifest.package_id()),
-            &self.path,
-            &package_path,
-        )?;
+        self.write(IndexChange::Publish(Box::new(manifest.clone())))
+    }
 
-        // Blow away the cache for this package, since we've now modified the
-        // underlying file.
+    /// Forget what we've read about a package, so that the next query reads it
+    /// from the index again. Useful after calling `update`.
+    pub fn clear_cached_package(&self, name: &PackageName) {
         let mut package_cache = self.package_cache.lock().unwrap();
-        package_cache.remove(&manifest.package.name);
-
-        Ok(())
+        package_cache.remove(name);
     }
 
     /// Read the list of versions for a package from the index.
@@ -170,23 +194,13 @@ impl PackageIndex {
             // and a package not existing.
             let file = File::open(&package_path)
                 .with_context(|| format!("could not open package {} from index", name))?;
-            let file = BufReader::new(file);
-
-            // Read all of the manifests from the package file.
-            //
-            // Entries into the index are stored as JSON Lines. This block will
-            // either parse all of the entries, or fail with a single error.
-            let manifest_stream: Result<Vec<Manifest>, serde_json::Error> =
-                serde_json::Deserializer::from_reader(file)
-                    .into_iter::<Manifest>()
-                    .collect();
-
-            let mut versions = manifest_stream
-                .with_context(|| format!("could not parse package index entry for {}", name))?;
+            let versions = parse_package_versions(BufReader::new(file), name)?;
+            let deprecation = read_deprecation(&self.path, name)?;
 
-            versions.sort_by(|a, b| b.package.version.cmp(&a.package.version));
-
-            let metadata = Arc::new(PackageMetadata { versions });
+            let metadata = Arc::new(PackageMetadata {
+                versions,
+                deprecation,
+            });
             package_cache.insert(name.clone(), Arc::clone(&metadata));
 
             Ok(metadata)
@@ -221,31 +235,34 @@ impl PackageIndex {
     /// Similar to publish, this first applies the change to our local copy
     /// and then attempts to push it to the remote index
     pub fn add_scope_owner(&self, scope: &str, owner_id: &u64) -> anyhow::Result<()> {
-        let repo = self.repository.lock().unwrap();
-        let mut path = self.path.clone();
-
-        // This scope might not exist yet
-        path.push(scope);
-        create_dir_all(&path)?;
-        path.push("owners.json");
-
-        {
-            let mut owners = self.get_scope_owners(&scope)?;
-            let mut file = OpenOptions::new().write(true).create(true).open(&path)?;
-
-            owners.push(*owner_id);
-            file.write_all(serde_json::to_string(&owners)?.as_bytes())?;
-        }
+        self.write(IndexChange::AddOwner {
+            scope: scope.to_owned(),
+            owner_id: *owner_id,
+        })
+    }
 
-        git_util::commit_and_push(
-            &repo,
-            self.access_token.clone(),
-            &format!("Add owner for {}/*", scope),
-            &self.path,
-            &path,
-        )?;
+    /// Remove an owner from a scope's owner file
+    /// Like adding an owner, this changes our local copy and then attempts to
+    /// push it to the remote index
+    pub fn remove_scope_owner(&self, scope: &str, owner_id: &u64) -> anyhow::Result<()> {
+        self.write(IndexChange::RemoveOwner {
+            scope: scope.to_owned(),
+            owner_id: *owner_id,
+        })
+    }
 
-        Ok(())
+    /// Mark a package as deprecated, or remove its deprecation when given
+    /// `None`. Like publishing, this changes our local copy and then attempts
+    /// to push it to the remote index.
+    pub fn set_deprecation(
+        &self,
+        name: &PackageName,
+        deprecation: Option<Deprecation>,
+    ) -> anyhow::Result<()> {
+        self.write(IndexChange::SetDeprecation {
+            name: name.clone(),
+            deprecation,
+        })
     }
 
     fn package_path(&self, name: &PackageName) -> PathBuf {
@@ -261,9 +278,90 @@ impl PackageIndex {
 #[derive(Default, Serialize)]
 pub struct PackageMetadata {
     pub versions: Vec<Manifest>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<Deprecation>,
+}
+
+/// A note from a package's owners that it should no longer be used. Unlike
+/// yanking, this applies to every version of the package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deprecation {
+    pub message: String,
+
+    /// A package to use instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<PackageName>,
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.message)?;
+
+        if let Some(replacement) = &self.replacement {
+            write!(formatter, " (use {} instead)", replacement)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read all of the manifests from a package's file in the index, newest
+/// version first.
+pub fn parse_package_versions(
+    reader: impl Read,
+    name: &PackageName,
+) -> anyhow::Result<Vec<Manifest>> {
+    // Entries into the index are stored as JSON Lines. This block will either
+    // parse all of the entries, or fail with a single error.
+    let manifest_stream: Result<Vec<Manifest>, serde_json::Error> =
+        serde_json::Deserializer::from_reader(reader)
+            .into_iter::<Manifest>()
+            .collect();
+
+    let mut versions = manifest_stream
+        .with_context(|| format!("could not parse package index entry for {}", name))?;
+
+    versions.sort_by(|a, b| b.package.version.cmp(&a.package.version));
+
+    Ok(versions)
+}
+
+/// Read the deprecation of a package from the index checked out at
+/// `index_path`, if it has one. Deprecations are stored for a whole scope at
+/// once, next to the scope's owners.
+pub fn read_deprecation(
+    index_path: &Path,
+    name: &PackageName,
+) -> anyhow::Result<Option<Deprecation>> {
+    let mut deprecations = read_deprecations(&deprecations_path(index_path, name.scope()))?;
+    Ok(deprecations.remove(name.name()))
+}
+
+fn read_deprecations(path: &Path) -> anyhow::Result<BTreeMap<String, Deprecation>> {
+    match File::open(path) {
+        Ok(file) => serde_json::from_reader(file)
+            .with_context(|| format!("could not parse deprecations at {}", path.display())),
+
+        Err(error) => match error.kind() {
+            ErrorKind::NotFound => Ok(BTreeMap::new()),
+            _ => Err(error)
+                .with_context(|| format!("failed to read deprecations at {}", path.display())),
+        },
+    }
+}
+
+fn deprecations_path(index_path: &Path, scope: &str) -> PathBuf {
+    index_path.join(scope).join("deprecations.json")
 }
 
 fn index_path(index_url: &Url) -> anyhow::Result<PathBuf> {
+    registry_cache_path("index", index_url)
+}
+
+/// Where to keep local state for a registry, like a clone of its index. Each
+/// kind of state gets its own directory.
+pub fn registry_cache_path(kind: &str, index_url: &Url) -> anyhow::Result<PathBuf> {
     let registry_name = match (index_url.domain(), index_url.scheme()) {
         (Some(domain), _) => domain,
         (None, "file") => "local-registry",
@@ -277,7 +375,7 @@ fn index_path(index_url: &Url) -> anyhow::Result<PathBuf> {
     let path = dirs::cache_dir()
         .ok_or_else(|| anyhow!("could not find cache directory"))?
         .join("gooey")
-        .join("index")
+        .join(kind)
         .join(ident);
 
     Ok(path)
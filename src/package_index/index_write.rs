@@ -0,0 +1,459 @@
+//! Writes to the package index go through a queue. Whoever holds the
+//! repository commits everything that's queued up in one go, so a burst of
+//! writes turns into a single commit and push.
+//!
+//! Changes are kept as what they mean rather than as Git commits. When a push
+//! is rejected because the remote index moved on, we reset to the remote and
+//! apply the same changes again on top of it, which can't conflict the way a
+//! textual rebase could.
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, format_err};
+use fs_err::{create_dir_all, File, OpenOptions};
+use git2::Repository;
+
+use crate::git_util;
+use crate::manifest::Manifest;
+use crate::package_name::PackageName;
+
+use super::{
+    deprecations_path, parse_package_versions, read_deprecations, Deprecation, PackageIndex,
+    PackageIndexConfig,
+};
+
+/// How many times to try pushing a batch of changes before giving up.
+const PUSH_ATTEMPTS: u32 = 5;
+
+/// How long to wait before the first retry. Each retry after that waits twice
+/// as long as the one before.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// A change to the contents of the index.
+pub(super) enum IndexChange {
+    Publish(Box<Manifest>),
+    AddOwner {
+        scope: String,
+        owner_id: u64,
+    },
+    RemoveOwner {
+        scope: String,
+        owner_id: u64,
+    },
+    SetDeprecation {
+        name: PackageName,
+        deprecation: Option<Deprecation>,
+    },
+    SetConfig(PackageIndexConfig),
+}
+
+impl IndexChange {
+    /// Make this change to the files of the index, returning the file that was
+    /// changed.
+    fn apply(&self, index: &PackageIndex) -> anyhow::Result<PathBuf> {
+        match self {
+            IndexChange::Publish(manifest) => {
+                let package_path = index.package_path(&manifest.package.name);
+
+                // This package might not exist yet, so create its containing
+                // directory.
+                create_dir_all(package_path.parent().unwrap())?;
+
+                // Someone else may have published the same version since this
+                // change was queued.
+                if package_path.exists() {
+                    let versions =
+                        parse_package_versions(File::open(&package_path)?, &manifest.package.name)?;
+
+                    if versions
+                        .iter()
+                        .any(|version| version.package.version == manifest.package.version)
+                    {
+                        bail!("package {} already exists in index", manifest.package_id());
+                    }
+                }
+
+                let mut file = OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(&package_path)?;
+
+                // Package entries are newline-delimited JSON files. We assume
+                // here that the file is empty or already ends in a newline.
+                let mut entry = serde_json::to_string(&manifest)?;
+                entry.push('\n');
+                file.write_all(entry.as_bytes())?;
+
+                Ok(package_path)
+            }
+            IndexChange::AddOwner { scope, owner_id } => {
+                let path = index.path.join(scope).join("owners.json");
+
+                // This scope might not exist yet
+                create_dir_all(path.parent().unwrap())?;
+
+                let mut owners = index.get_scope_owners(scope)?;
+                if !owners.contains(owner_id) {
+                    owners.push(*owner_id);
+                    fs_err::write(&path, serde_json::to_string(&owners)?)?;
+                }
+
+                Ok(path)
+            }
+            IndexChange::RemoveOwner { scope, owner_id } => {
+                let path = index.path.join(scope).join("owners.json");
+
+                let mut owners = index.get_scope_owners(scope)?;
+                let count = owners.len();
+                owners.retain(|owner| owner != owner_id);
+
+                if owners.len() == count {
+                    bail!("{} is not an owner of scope {}", owner_id, scope);
+                }
+
+                fs_err::write(&path, serde_json::to_string(&owners)?)?;
+                Ok(path)
+            }
+            IndexChange::SetDeprecation { name, deprecation } => {
+                let path = deprecations_path(&index.path, name.scope());
+                create_dir_all(path.parent().unwrap())?;
+
+                let mut deprecations = read_deprecations(&path)?;
+                match deprecation {
+                    Some(deprecation) => {
+                        deprecations.insert(name.name().to_owned(), deprecation.clone());
+                    }
+                    None => {
+                        deprecations.remove(name.name());
+                    }
+                }
+
+                fs_err::write(&path, serde_json::to_string(&deprecations)?)?;
+                Ok(path)
+            }
+            IndexChange::SetConfig(config) => {
+                let path = index.path.join("config.json");
+
+                fs_err::write(&path, serde_json::to_string_pretty(config)?)?;
+                Ok(path)
+            }
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            IndexChange::Publish(manifest) => format!("Publish {}", manifest.package_id()),
+            IndexChange::AddOwner { scope, .. } => format!("Add owner for {}/*", scope),
+            IndexChange::RemoveOwner { scope, .. } => format!("Remove owner for {}/*", scope),
+            IndexChange::SetDeprecation {
+                name,
+                deprecation: Some(_),
+            } => format!("Deprecate {}", name),
+            IndexChange::SetDeprecation {
+                name,
+                deprecation: None,
+            } => format!("Remove deprecation of {}", name),
+            IndexChange::SetConfig(_) => "Update config.json".to_owned(),
+        }
+    }
+
+    /// The package whose cached metadata is out of date after this change.
+    fn package_name(&self) -> Option<&PackageName> {
+        match self {
+            IndexChange::Publish(manifest) => Some(&manifest.package.name),
+            IndexChange::SetDeprecation { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// A change waiting to be written, along with where to send the result.
+pub(super) struct PendingWrite {
+    change: IndexChange,
+    result: Sender<anyhow::Result<()>>,
+}
+
+impl PendingWrite {
+    fn finish(self, result: anyhow::Result<()>) {
+        // The writer waits for its result before letting go of the receiver,
+        // so there's always someone listening.
+        let _ = self.result.send(result);
+    }
+}
+
+impl PackageIndex {
+    /// Queue up a change to the index and wait until it's been committed and
+    /// pushed, possibly along with other changes queued at the same time.
+    pub(super) fn write(&self, change: IndexChange) -> anyhow::Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.pending_writes.lock().unwrap().push(PendingWrite {
+            change,
+            result: sender,
+        });
+
+        let repo = self.repository.lock().unwrap();
+
+        // While we were waiting for the repository, whoever had it may have
+        // written our change along with theirs.
+        if let Ok(result) = receiver.try_recv() {
+            return result;
+        }
+
+        let batch = mem::take(&mut *self.pending_writes.lock().unwrap());
+        self.write_batch(&repo, batch);
+
+        receiver
+            .try_recv()
+            .unwrap_or_else(|_| Err(format_err!("index write was never finished")))
+    }
+
+    fn write_batch(&self, repo: &Repository, mut batch: Vec<PendingWrite>) {
+        let mut attempt = 1;
+
+        loop {
+            let mut applied = Vec::new();
+            let mut modified_files = Vec::new();
+
+            // A change that can't be made, like publishing a version that
+            // already exists, only fails on its own.
+            for write in batch {
+                match write.change.apply(self) {
+                    Ok(path) => {
+                        modified_files.push(path);
+                        applied.push(write);
+                    }
+                    Err(err) => write.finish(Err(err)),
+                }
+            }
+
+            if applied.is_empty() {
+                return;
+            }
+
+            let changes: Vec<&IndexChange> = applied.iter().map(|write| &write.change).collect();
+            let result = self.commit_and_push(repo, &batch_message(&changes), &modified_files);
+
+            let err = match result {
+                Ok(()) => {
+                    let mut package_cache = self.package_cache.lock().unwrap();
+                    for write in applied {
+                        if let Some(name) = write.change.package_name() {
+                            package_cache.remove(name);
+                        }
+                        write.finish(Ok(()));
+                    }
+                    return;
+                }
+                Err(err) => err,
+            };
+
+            // Local indexes don't push, so there's nothing to retry.
+            if self.local || attempt >= PUSH_ATTEMPTS {
+                if !self.local {
+                    // Don't leave commits that never made it to the remote
+                    // lying around in our copy.
+                    if let Err(reset_err) = self.reset_to_remote(repo) {
+                        log::error!("could not reset package index: {:?}", reset_err);
+                    }
+                }
+
+                let message = format!("{:?}", err);
+                for write in applied {
+                    write.finish(Err(format_err!("{}", message)));
+                }
+                return;
+            }
+
+            log::warn!(
+                "could not push to package index (attempt {} of {}), retrying: {:?}",
+                attempt,
+                PUSH_ATTEMPTS,
+                err
+            );
+            thread::sleep(INITIAL_BACKOFF * 2u32.pow(attempt - 1));
+            attempt += 1;
+
+            // Most likely the remote index has moved on. Start again from
+            // where it is now, and make our changes on top of that.
+            if let Err(err) = self.reset_to_remote(repo) {
+                let message = format!("{:?}", err);
+                for write in applied {
+                    write.finish(Err(format_err!("{}", message)));
+                }
+                return;
+            }
+
+            batch = applied;
+        }
+    }
+
+    fn commit_and_push(
+        &self,
+        repo: &Repository,
+        message: &str,
+        modified_files: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        git_util::commit(repo, message, &self.path, modified_files)?;
+
+        if !self.local {
+            git_util::push(repo, self.credentials.clone(), "origin", "refs/heads/main")?;
+        }
+
+        Ok(())
+    }
+
+    fn reset_to_remote(&self, repo: &Repository) -> anyhow::Result<()> {
+        git_util::update_index(self.credentials.clone(), repo)?;
+
+        // Anything could have changed on the remote, so none of what we've
+        // read can be trusted anymore.
+        self.package_cache.lock().unwrap().clear();
+
+        Ok(())
+    }
+}
+
+fn batch_message(changes: &[&IndexChange]) -> String {
+    if let [change] = changes {
+        return change.message();
+    }
+
+    let mut message = format!("Update {} index entries\n\n", changes.len());
+    for change in changes {
+        writeln!(message, "{}", change.message()).unwrap();
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use url::Url;
+
+    use super::*;
+    use crate::git_util::GitCredentials;
+    use crate::test_package::PackageBuilder;
+
+    fn init_remote(path: &Path) -> Url {
+        let repo = Repository::init_bare(path).unwrap();
+        let sig = git2::Signature::now("PackageUser", "PackageUser@localhost").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+
+        repo.set_head("refs/heads/main").unwrap();
+        repo.commit(
+            Some("refs/heads/main"),
+            &sig,
+            &sig,
+            "Initial commit",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        Url::from_directory_path(path).unwrap()
+    }
+
+    fn remote_history(url: &Url) -> Vec<String> {
+        let repo = Repository::open(url.to_file_path().unwrap()).unwrap();
+        let mut commit = repo.head().unwrap().peel_to_commit().unwrap();
+        let mut messages = vec![commit.message().unwrap().to_owned()];
+
+        while let Ok(parent) = commit.parent(0) {
+            messages.push(parent.message().unwrap().to_owned());
+            commit = parent;
+        }
+
+        messages
+    }
+
+    fn manifest(id: &str) -> Manifest {
+        PackageBuilder::new(id).into_manifest()
+    }
+
+    #[test]
+    fn rebases_onto_remote() {
+        let remote_dir = tempfile::tempdir().unwrap();
+        let remote = init_remote(remote_dir.path());
+        let first = PackageIndex::new_temp(&remote, GitCredentials::default()).unwrap();
+        let second = PackageIndex::new_temp(&remote, GitCredentials::default()).unwrap();
+
+        first.publish(&manifest("biff/first@1.0.0")).unwrap();
+        second.publish(&manifest("biff/second@1.0.0")).unwrap();
+
+        assert_eq!(
+            remote_history(&remote),
+            vec![
+                "Publish biff/second@1.0.0",
+                "Publish biff/first@1.0.0",
+                "Initial commit"
+            ]
+        );
+
+        first.update().unwrap();
+        let name = manifest("biff/second@1.0.0").package.name;
+        assert_eq!(first.get_package_metadata(&name).unwrap().versions.len(), 1);
+    }
+
+    #[test]
+    fn duplicate_after_rebase() {
+        let remote_dir = tempfile::tempdir().unwrap();
+        let remote = init_remote(remote_dir.path());
+        let first = PackageIndex::new_temp(&remote, GitCredentials::default()).unwrap();
+        let second = PackageIndex::new_temp(&remote, GitCredentials::default()).unwrap();
+
+        first.publish(&manifest("biff/hello@1.0.0")).unwrap();
+
+        let duplicate = PackageBuilder::new("biff/hello@1.0.0")
+            .with_dep("Other", "biff/other@1.0.0")
+            .into_manifest();
+        let err = second.publish(&duplicate).unwrap_err();
+
+        assert!(err.to_string().contains("already exists"), "{}", err);
+        assert_eq!(remote_history(&remote).len(), 2);
+    }
+
+    #[test]
+    fn batches_queued_writes() {
+        let remote_dir = tempfile::tempdir().unwrap();
+        let remote = init_remote(remote_dir.path());
+        let index = Arc::new(PackageIndex::new_temp(&remote, GitCredentials::default()).unwrap());
+
+        // Hold onto the repository so that the writes queue up behind it.
+        let repo = index.repository.lock().unwrap();
+        let writers: Vec<_> = ["biff/a@1.0.0", "biff/b@1.0.0", "biff/c@1.0.0"]
+            .iter()
+            .map(|id| {
+                let index = Arc::clone(&index);
+                thread::spawn(move || index.publish(&manifest(id)))
+            })
+            .collect();
+
+        while index.pending_writes.lock().unwrap().len() < 3 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(repo);
+
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+
+        let history = remote_history(&remote);
+        assert_eq!(history.len(), 2);
+        assert!(
+            history[0].starts_with("Update 3 index entries"),
+            "{}",
+            history[0]
+        );
+    }
+}
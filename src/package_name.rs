@@ -46,7 +46,8 @@ impl PackageName {
     }
 }
 
-fn validate_scope(scope: &str) -> anyhow::Result<()> {
+/// Check that a scope, the first half of a package name, is valid.
+pub fn validate_scope(scope: &str) -> anyhow::Result<()> {
     let only_valid_chars = scope
         .chars()
         .all(|char| char.is_ascii_lowercase() || char.is_ascii_digit() || char == '-');
@@ -3,7 +3,7 @@ use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context};
 use semver::{Version, VersionReq};
-use serde::de::{Deserialize, Deserializer, Error, Visitor};
+use serde::de::{Deserialize, Deserializer, Error, MapAccess, Visitor};
 use serde::ser::{Serialize, Serializer};
 
 use crate::package_id::PackageId;
@@ -116,6 +116,13 @@ impl<'de> Visitor<'de> for PackageReqVisitor {
     fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
         value.parse().map_err(|err| E::custom(err))
     }
+
+    fn visit_map<A: MapAccess<'de>>(self, _map: A) -> Result<Self::Value, A::Error> {
+        Err(A::Error::custom(
+            "path and git dependencies are not supported, dependencies must be registry \
+             packages of the form SCOPE/NAME@VERSION_REQ",
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -178,6 +185,22 @@ mod test {
         no_version_at.unwrap_err();
     }
 
+    #[test]
+    fn reject_path_dependencies() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Dependencies {
+            #[allow(dead_code)]
+            local: PackageReq,
+        }
+
+        let err = toml::from_str::<Dependencies>(r#"local = { path = "../local" }"#).unwrap_err();
+        assert!(
+            err.to_string().contains("path and git dependencies"),
+            "{}",
+            err
+        );
+    }
+
     #[test]
     fn serialization() {
         let name = PackageName::new("lpghatguy", "asink").unwrap();
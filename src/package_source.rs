@@ -1,33 +1,55 @@
 mod in_memory;
 mod registry;
+mod sparse_registry;
 mod test_registry;
 
 pub use self::in_memory::InMemoryRegistry;
 use self::in_memory::InMemoryRegistrySource;
 pub use self::registry::Registry;
+pub use self::sparse_registry::SparseRegistry;
 pub use self::test_registry::TestRegistry;
 
 use std::collections::HashMap;
 use std::path::PathBuf;
 
 use serde::Serialize;
+use url::Url;
 
+use crate::auth::AuthStore;
+
+use crate::config::DownloadConfig;
 use crate::manifest::Manifest;
 use crate::package_contents::PackageContents;
 use crate::package_id::PackageId;
+use crate::package_index::{Deprecation, PackageIndex, PackageIndexConfig};
+use crate::package_name::PackageName;
 use crate::package_req::PackageReq;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub enum PackageSourceId {
     DefaultRegistry,
     Git(String),
+    Sparse(String),
     Path(PathBuf),
 }
 
+impl PackageSourceId {
+    /// Identify a registry by its spec, which is a sparse index if it starts
+    /// with `sparse+` and a Git index otherwise.
+    pub fn for_registry(spec: String) -> Self {
+        if SparseRegistry::is_sparse_spec(&spec) {
+            PackageSourceId::Sparse(spec)
+        } else {
+            PackageSourceId::Git(spec)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PackageSourceMap {
     sources: HashMap<PackageSourceId, Box<PackageSource>>,
     source_order: Vec<PackageSourceId>,
+    download_config: DownloadConfig,
 }
 
 impl PackageSourceMap {
@@ -38,9 +60,21 @@ impl PackageSourceMap {
         Self {
             sources,
             source_order: vec![PackageSourceId::DefaultRegistry],
+            download_config: DownloadConfig::default(),
         }
     }
 
+    /// Use the given download settings for any sources added to this map
+    /// later, like fallback registries.
+    pub fn with_download_config(mut self, download_config: DownloadConfig) -> Self {
+        self.download_config = download_config;
+        self
+    }
+
+    pub fn download_config(&self) -> &DownloadConfig {
+        &self.download_config
+    }
+
     pub fn get(&self, id: &PackageSourceId) -> Option<&PackageSource> {
         self.sources.get(id).map(|source| source.as_ref())
     }
@@ -62,9 +96,9 @@ impl PackageSourceMap {
                 // Prevent circular references by only adding new sources
                 if !self.source_order.contains(&fallback) {
                     let source: Box<PackageSource> = match &fallback {
-                        PackageSourceId::Git(url) => {
-                            Box::new(PackageSource::Registry(Registry::from_registry_spec(url)?))
-                        }
+                        PackageSourceId::Git(url) | PackageSourceId::Sparse(url) => Box::new(
+                            PackageSource::from_registry_spec(url, &self.download_config)?,
+                        ),
                         PackageSourceId::Path(path) => {
                             Box::new(PackageSource::TestRegistry(TestRegistry::new(path.clone())))
                         }
@@ -99,20 +133,57 @@ pub trait PackageSourceProvider: Sync + Send + Clone {
 
     /// Provide a list of fallback sources to search if this source can't provide a package
     fn fallback_sources(&self) -> anyhow::Result<Vec<PackageSourceId>>;
+
+    /// Find out whether a package has been deprecated by its owners.
+    fn deprecation(&self, _name: &PackageName) -> anyhow::Result<Option<Deprecation>> {
+        Ok(None)
+    }
+}
+
+/// Read the config of the registry with the given spec, whichever kind of index
+/// it uses.
+pub fn registry_config(spec: &str) -> anyhow::Result<PackageIndexConfig> {
+    if SparseRegistry::is_sparse_spec(spec) {
+        SparseRegistry::from_registry_spec(spec)?.index_config()
+    } else {
+        let url = Url::parse(spec)?;
+        PackageIndex::new(&url, AuthStore::git_credentials(&url)?)?.config()
+    }
 }
 
 #[derive(Clone)]
 pub enum PackageSource {
     InMemory(InMemoryRegistrySource),
     Registry(Registry),
+    Sparse(SparseRegistry),
     TestRegistry(TestRegistry),
 }
 
+impl PackageSource {
+    /// Create the right kind of registry for a registry spec, which usually
+    /// comes from the `registry` field of a package manifest.
+    pub fn from_registry_spec(
+        spec: &str,
+        download_config: &DownloadConfig,
+    ) -> anyhow::Result<Self> {
+        if SparseRegistry::is_sparse_spec(spec) {
+            Ok(PackageSource::Sparse(
+                SparseRegistry::from_registry_spec(spec)?.with_download_config(download_config)?,
+            ))
+        } else {
+            Ok(PackageSource::Registry(
+                Registry::from_registry_spec(spec)?.with_download_config(download_config)?,
+            ))
+        }
+    }
+}
+
 impl PackageSourceProvider for PackageSource {
     fn update(&self) -> anyhow::Result<()> {
         match self {
             PackageSource::InMemory(source) => source.update(),
             PackageSource::Registry(source) => source.update(),
+            PackageSource::Sparse(source) => source.update(),
             PackageSource::TestRegistry(source) => source.update(),
         }
     }
@@ -121,6 +192,7 @@ impl PackageSourceProvider for PackageSource {
         match self {
             PackageSource::InMemory(source) => source.query(package_req),
             PackageSource::Registry(source) => source.query(package_req),
+            PackageSource::Sparse(source) => source.query(package_req),
             PackageSource::TestRegistry(source) => source.query(package_req),
         }
     }
@@ -129,6 +201,7 @@ impl PackageSourceProvider for PackageSource {
         match self {
             PackageSource::InMemory(source) => source.download_package(package_id),
             PackageSource::Registry(source) => source.download_package(package_id),
+            PackageSource::Sparse(source) => source.download_package(package_id),
             PackageSource::TestRegistry(source) => source.download_package(package_id),
         }
     }
@@ -137,7 +210,17 @@ impl PackageSourceProvider for PackageSource {
         match self {
             PackageSource::InMemory(source) => source.fallback_sources(),
             PackageSource::Registry(source) => source.fallback_sources(),
+            PackageSource::Sparse(source) => source.fallback_sources(),
             PackageSource::TestRegistry(source) => source.fallback_sources(),
         }
     }
+
+    fn deprecation(&self, name: &PackageName) -> anyhow::Result<Option<Deprecation>> {
+        match self {
+            PackageSource::InMemory(source) => source.deprecation(name),
+            PackageSource::Registry(source) => source.deprecation(name),
+            PackageSource::Sparse(source) => source.deprecation(name),
+            PackageSource::TestRegistry(source) => source.deprecation(name),
+        }
+    }
 }
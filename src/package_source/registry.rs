@@ -1,15 +1,22 @@
 use std::io::Read;
 use std::sync::Arc;
+use std::thread::sleep;
 
-use anyhow::bail;
+use anyhow::{bail, format_err};
 use once_cell::sync::OnceCell;
-use reqwest::{blocking::Client, header::AUTHORIZATION};
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    header::AUTHORIZATION,
+    StatusCode,
+};
 use url::Url;
 
 use crate::auth::AuthStore;
+use crate::config::DownloadConfig;
 use crate::manifest::Manifest;
 use crate::package_id::PackageId;
-use crate::package_index::PackageIndex;
+use crate::package_index::{Deprecation, PackageIndex};
+use crate::package_name::PackageName;
 use crate::package_req::PackageReq;
 use crate::package_source::PackageContents;
 
@@ -23,6 +30,7 @@ pub struct Registry {
     auth_token: OnceCell<Option<Arc<str>>>,
     index: OnceCell<Arc<PackageIndex>>,
     client: Client,
+    download_config: DownloadConfig,
 }
 
 impl Registry {
@@ -36,9 +44,23 @@ impl Registry {
             auth_token: OnceCell::new(),
             index: OnceCell::new(),
             client: Client::new(),
+            download_config: DownloadConfig::default(),
         })
     }
 
+    /// Apply timeouts and retry settings to requests made by this registry.
+    pub fn with_download_config(
+        mut self,
+        download_config: &DownloadConfig,
+    ) -> anyhow::Result<Self> {
+        self.client = Client::builder()
+            .timeout(download_config.timeout())
+            .build()?;
+        self.download_config = download_config.clone();
+
+        Ok(self)
+    }
+
     fn auth_token(&self) -> anyhow::Result<Option<Arc<str>>> {
         self.auth_token
             .get_or_try_init(|| match AuthStore::get_token(self.api_url()?.as_str())? {
@@ -49,8 +71,10 @@ impl Registry {
     }
 
     fn index(&self) -> anyhow::Result<&Arc<PackageIndex>> {
-        self.index
-            .get_or_try_init(|| Ok(Arc::new(PackageIndex::new(&self.index_url, None)?)))
+        self.index.get_or_try_init(|| {
+            let credentials = AuthStore::git_credentials(&self.index_url)?;
+            Ok(Arc::new(PackageIndex::new(&self.index_url, credentials)?))
+        })
     }
 
     fn api_url(&self) -> anyhow::Result<Url> {
@@ -79,36 +103,13 @@ impl PackageSourceProvider for Registry {
     }
 
     fn download_package(&self, package_id: &PackageId) -> anyhow::Result<PackageContents> {
-        let path = format!(
-            "/v1/package-contents/{}/{}/{}",
-            package_id.name().scope(),
-            package_id.name().name(),
-            package_id.version()
-        );
-
-        let url = self.api_url()?.join(&path)?;
-
-        let mut request = self.client.get(url).header("gooey-Version", VERSION);
-
-        if let Some(token) = self.auth_token()? {
-            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
-        }
-        let mut response = request.send()?;
-
-        if !response.status().is_success() {
-            bail!(
-                "Failed to download package {} from registry: {}\n{} {}",
-                package_id,
-                self.api_url()?,
-                response.status(),
-                response.text()?
-            );
-        }
-
-        let mut data = Vec::new();
-        response.read_to_end(&mut data)?;
-
-        Ok(PackageContents::from_buffer(data))
+        download_from_api(
+            &self.client,
+            &self.download_config,
+            &self.api_url()?,
+            self.auth_token()?.as_deref(),
+            package_id,
+        )
     }
 
     fn fallback_sources(&self) -> anyhow::Result<Vec<PackageSourceId>> {
@@ -116,9 +117,107 @@ impl PackageSourceProvider for Registry {
 
         let sources = fallback_registries
             .into_iter()
-            .map(PackageSourceId::Git)
+            .map(PackageSourceId::for_registry)
             .collect();
 
         Ok(sources)
     }
+
+    fn deprecation(&self, name: &PackageName) -> anyhow::Result<Option<Deprecation>> {
+        let metadata = self.index()?.get_package_metadata(name)?;
+        Ok(metadata.deprecation.clone())
+    }
+}
+
+/// Download the contents of a package from a registry's API.
+pub(super) fn download_from_api(
+    client: &Client,
+    download_config: &DownloadConfig,
+    api: &Url,
+    auth_token: Option<&str>,
+    package_id: &PackageId,
+) -> anyhow::Result<PackageContents> {
+    let path = format!(
+        "/v1/package-contents/{}/{}/{}",
+        package_id.name().scope(),
+        package_id.name().name(),
+        package_id.version()
+    );
+
+    let url = api.join(&path)?;
+    let description = format!("Download of {}", package_id);
+
+    let mut response = send_with_retries(download_config, &description, || {
+        let mut request = client.get(url.clone()).header("gooey-Version", VERSION);
+
+        if let Some(token) = auth_token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        request
+    })
+    .map_err(|err| format_err!("Failed to download package {}: {}", package_id, err))?;
+
+    if !response.status().is_success() {
+        bail!(
+            "Failed to download package {} from registry: {}\n{} {}",
+            package_id,
+            api,
+            response.status(),
+            response.text()?
+        );
+    }
+
+    let mut data = Vec::new();
+    response.read_to_end(&mut data)?;
+
+    Ok(PackageContents::from_buffer(data))
+}
+
+/// Send a request, retrying failures that look temporary with the backoff
+/// from `download_config`.
+pub(super) fn send_with_retries(
+    download_config: &DownloadConfig,
+    description: &str,
+    make_request: impl Fn() -> RequestBuilder,
+) -> reqwest::Result<Response> {
+    let mut attempt = 0;
+
+    loop {
+        let result = make_request().send();
+
+        if attempt < download_config.retries && is_transient(&result) {
+            attempt += 1;
+            let backoff = download_config.backoff(attempt);
+
+            log::debug!(
+                "{} failed, retrying in {}ms (attempt {} of {})",
+                description,
+                backoff.as_millis(),
+                attempt,
+                download_config.retries
+            );
+
+            sleep(backoff);
+            continue;
+        }
+
+        return result;
+    }
+}
+
+/// Whether a failed request is worth retrying. Connection problems, timeouts,
+/// and server-side errors are usually temporary, but a 404 or 401 will not fix
+/// itself.
+fn is_transient(result: &reqwest::Result<Response>) -> bool {
+    match result {
+        Ok(response) => {
+            let status = response.status();
+
+            status.is_server_error()
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || status == StatusCode::REQUEST_TIMEOUT
+        }
+        Err(err) => err.is_timeout() || err.is_connect(),
+    }
 }
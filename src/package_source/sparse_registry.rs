@@ -0,0 +1,507 @@
+//! A registry whose index is served over plain HTTP, one file per package,
+//! instead of as a Git repository. Only the files needed to resolve a project
+//! are downloaded. They're cached on disk and revalidated with ETags, so
+//! unchanged files cost a single small request.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, format_err, Context};
+use once_cell::sync::OnceCell;
+use reqwest::{
+    blocking::Client,
+    header::{AUTHORIZATION, ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
+use semver::Version;
+use url::Url;
+
+use crate::auth::AuthStore;
+use crate::config::DownloadConfig;
+use crate::manifest::Manifest;
+use crate::package_id::PackageId;
+use crate::package_index::{
+    parse_package_versions, registry_cache_path, Deprecation, PackageIndexConfig,
+};
+use crate::package_name::PackageName;
+use crate::package_req::PackageReq;
+use crate::package_source::PackageContents;
+
+use super::registry::{download_from_api, send_with_retries};
+use super::{PackageSourceId, PackageSourceProvider};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The contents of an index file, or `None` if the registry doesn't have it.
+type FetchedFile = Option<Arc<Vec<u8>>>;
+
+/// Registry specs starting with this use the sparse protocol, like
+/// `sparse+https://registry.example.com/v1/index/`.
+pub const SPARSE_PREFIX: &str = "sparse+";
+
+#[derive(Clone)]
+pub struct SparseRegistry {
+    /// Where the index files are served from, always ending in a slash.
+    index_url: Url,
+
+    /// Where fetched index files and their ETags are kept between runs.
+    cache_path: PathBuf,
+
+    config: OnceCell<Arc<PackageIndexConfig>>,
+    auth_token: OnceCell<Option<Arc<str>>>,
+    client: Client,
+    download_config: DownloadConfig,
+
+    /// Files that have already been fetched during this run, so that each one
+    /// is requested at most once until the next update.
+    fetched: Arc<Mutex<HashMap<String, FetchedFile>>>,
+}
+
+impl SparseRegistry {
+    pub fn is_sparse_spec(spec: &str) -> bool {
+        spec.starts_with(SPARSE_PREFIX)
+    }
+
+    /// Create a `SparseRegistry` from a `sparse+` registry spec.
+    pub fn from_registry_spec(spec: &str) -> anyhow::Result<Self> {
+        let index_url = parse_spec(spec)?;
+        let cache_path = registry_cache_path("sparse", &index_url)?;
+
+        Ok(Self::new(index_url, cache_path))
+    }
+
+    fn new(index_url: Url, cache_path: PathBuf) -> Self {
+        Self {
+            index_url,
+            cache_path,
+            config: OnceCell::new(),
+            auth_token: OnceCell::new(),
+            client: Client::new(),
+            download_config: DownloadConfig::default(),
+            fetched: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Apply timeouts and retry settings to requests made by this registry.
+    pub fn with_download_config(
+        mut self,
+        download_config: &DownloadConfig,
+    ) -> anyhow::Result<Self> {
+        self.client = Client::builder()
+            .timeout(download_config.timeout())
+            .build()?;
+        self.download_config = download_config.clone();
+
+        Ok(self)
+    }
+
+    /// The spec this registry was created from, like `sparse+https://...`.
+    pub fn spec(&self) -> String {
+        format!("{}{}", SPARSE_PREFIX, self.index_url)
+    }
+
+    /// The registry's `config.json`, which says where its API lives.
+    pub fn index_config(&self) -> anyhow::Result<PackageIndexConfig> {
+        Ok(PackageIndexConfig::clone(self.config()?))
+    }
+
+    fn config(&self) -> anyhow::Result<&Arc<PackageIndexConfig>> {
+        self.config.get_or_try_init(|| {
+            let contents = self
+                .fetch("config.json")?
+                .with_context(|| format!("registry {} has no config.json", self.index_url))?;
+
+            let config = serde_json::from_slice(&contents)
+                .with_context(|| format!("invalid config.json in registry {}", self.index_url))?;
+
+            Ok(Arc::new(config))
+        })
+    }
+
+    fn auth_token(&self) -> anyhow::Result<Option<Arc<str>>> {
+        self.auth_token
+            .get_or_try_init(|| {
+                let api = &self.config()?.api;
+                Ok(AuthStore::get_token(api.as_str())?.map(|token| Arc::from(token.as_str())))
+            })
+            .cloned()
+    }
+
+    /// Fetch a file from the index, or `None` if it doesn't exist.
+    fn fetch(&self, path: &str) -> anyhow::Result<FetchedFile> {
+        if let Some(contents) = self.fetched.lock().unwrap().get(path) {
+            return Ok(contents.clone());
+        }
+
+        let contents = self.fetch_uncached(path)?.map(Arc::new);
+
+        self.fetched
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), contents.clone());
+
+        Ok(contents)
+    }
+
+    fn fetch_uncached(&self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let url = self.index_url.join(path)?;
+        let cached_path = self.cache_path.join(path);
+        let etag_path = etag_path(&cached_path);
+
+        let cached = read_optional(&cached_path)?;
+        let etag = match cached {
+            Some(_) => read_optional(&etag_path)?.and_then(|etag| String::from_utf8(etag).ok()),
+            None => None,
+        };
+
+        let send = |auth_token: Option<&str>| {
+            let description = format!("Fetching {}", url);
+
+            send_with_retries(&self.download_config, &description, || {
+                let mut request = self
+                    .client
+                    .get(url.clone())
+                    .header("gooey-Version", VERSION);
+
+                if let Some(etag) = &etag {
+                    request = request.header(IF_NONE_MATCH, etag.as_str());
+                }
+
+                if let Some(token) = auth_token {
+                    request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+                }
+
+                request
+            })
+        };
+
+        // Public registries don't need a token, and finding one means fetching
+        // the config first, so only use one when the registry asks for it.
+        let mut result = send(None);
+
+        if let Ok(response) = &result {
+            if response.status() == StatusCode::UNAUTHORIZED && path != "config.json" {
+                if let Some(token) = self.auth_token()? {
+                    result = send(Some(&token));
+                }
+            }
+        }
+
+        let response = match (result, cached) {
+            (Ok(response), cached) => {
+                if response.status() == StatusCode::NOT_MODIFIED {
+                    if let Some(cached) = cached {
+                        return Ok(Some(cached));
+                    }
+                }
+
+                response
+            }
+
+            // Keep working offline with whatever was fetched last time.
+            (Err(err), Some(cached)) => {
+                log::warn!("Could not fetch {}, using cached copy: {}", url, err);
+                return Ok(Some(cached));
+            }
+
+            (Err(err), None) => return Err(format_err!("Failed to fetch {}: {}", url, err)),
+        };
+
+        let status = response.status();
+
+        if status == StatusCode::NOT_FOUND {
+            remove_optional(&cached_path)?;
+            remove_optional(&etag_path)?;
+            return Ok(None);
+        }
+
+        if !status.is_success() {
+            bail!(
+                "Failed to fetch {} from registry\n{} {}",
+                url,
+                status,
+                response.text()?
+            );
+        }
+
+        let new_etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_owned);
+
+        let contents = response.bytes()?.to_vec();
+
+        fs_err::create_dir_all(cached_path.parent().unwrap())?;
+        fs_err::write(&cached_path, &contents)?;
+
+        match new_etag {
+            Some(etag) => fs_err::write(&etag_path, etag)?,
+            None => remove_optional(&etag_path)?,
+        }
+
+        Ok(Some(contents))
+    }
+
+    /// Whether a version of a package is in the index. Only knows about
+    /// changes since the last call to `update`.
+    pub fn has_version(&self, name: &PackageName, version: &Version) -> anyhow::Result<bool> {
+        let path = format!("{}/{}", name.scope(), name.name());
+
+        match self.fetch(&path)? {
+            Some(contents) => Ok(parse_package_versions(contents.as_slice(), name)?
+                .iter()
+                .any(|manifest| &manifest.package.version == version)),
+            None => Ok(false),
+        }
+    }
+
+    fn package_versions(&self, name: &PackageName) -> anyhow::Result<Vec<Manifest>> {
+        let path = format!("{}/{}", name.scope(), name.name());
+
+        let contents = self
+            .fetch(&path)?
+            .with_context(|| format!("could not find package {} in index", name))?;
+
+        parse_package_versions(contents.as_slice(), name)
+    }
+}
+
+impl PackageSourceProvider for SparseRegistry {
+    fn update(&self) -> anyhow::Result<()> {
+        // Files are revalidated the first time they're needed, so forgetting
+        // what was fetched is all that's needed.
+        self.fetched.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn query(&self, package_req: &PackageReq) -> anyhow::Result<Vec<Manifest>> {
+        let versions = self
+            .package_versions(package_req.name())?
+            .into_iter()
+            .filter(|manifest| {
+                package_req.matches(&manifest.package.name, &manifest.package.version)
+            })
+            .collect();
+
+        Ok(versions)
+    }
+
+    fn download_package(&self, package_id: &PackageId) -> anyhow::Result<PackageContents> {
+        download_from_api(
+            &self.client,
+            &self.download_config,
+            &self.config()?.api,
+            self.auth_token()?.as_deref(),
+            package_id,
+        )
+    }
+
+    fn fallback_sources(&self) -> anyhow::Result<Vec<PackageSourceId>> {
+        let sources = self
+            .config()?
+            .fallback_registries
+            .iter()
+            .cloned()
+            .map(PackageSourceId::for_registry)
+            .collect();
+
+        Ok(sources)
+    }
+
+    fn deprecation(&self, name: &PackageName) -> anyhow::Result<Option<Deprecation>> {
+        let path = format!("{}/deprecations.json", name.scope());
+
+        let mut deprecations: BTreeMap<String, Deprecation> = match self.fetch(&path)? {
+            Some(contents) => serde_json::from_slice(&contents)
+                .with_context(|| format!("could not parse deprecations for {}", name.scope()))?,
+            None => BTreeMap::new(),
+        };
+
+        Ok(deprecations.remove(name.name()))
+    }
+}
+
+fn parse_spec(spec: &str) -> anyhow::Result<Url> {
+    let url = spec
+        .strip_prefix(SPARSE_PREFIX)
+        .with_context(|| format!("{} is not a sparse registry", spec))?;
+
+    let mut url = Url::parse(url)?;
+
+    // Index files are joined onto this URL, which only keeps the last path
+    // segment if it ends in a slash.
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+
+    Ok(url)
+}
+
+fn etag_path(cached_path: &Path) -> PathBuf {
+    let mut path = OsString::from(cached_path);
+    path.push(".etag");
+    PathBuf::from(path)
+}
+
+fn read_optional(path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    match fs_err::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn remove_optional(path: &Path) -> anyhow::Result<()> {
+    match fs_err::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const MINIMAL: &str = include_str!("../../test-registries/primary-registry/index/biff/minimal");
+    const DEPRECATIONS: &str =
+        include_str!("../../test-registries/primary-registry/index/biff/deprecations.json");
+
+    /// A request seen by the test server: its path, and whether it was
+    /// answered with 304 Not Modified.
+    type RequestLog = Arc<Mutex<Vec<(String, bool)>>>;
+
+    /// Serve index files over HTTP, using the length of each file as its ETag.
+    fn serve(files: Vec<(&'static str, &'static str)>) -> (Url, RequestLog) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/index/", listener.local_addr().unwrap());
+        let log = RequestLog::default();
+        let server_log = Arc::clone(&log);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap();
+                let path = path.trim_start_matches("/index/").to_owned();
+
+                let mut if_none_match = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+
+                    let (name, value) = line.split_once(':').unwrap();
+                    if name.eq_ignore_ascii_case("if-none-match") {
+                        if_none_match = Some(value.trim().to_owned());
+                    }
+                }
+
+                let file = files.iter().find(|(file_path, _)| *file_path == path);
+                let (status, etag, body) = match file {
+                    Some((_, contents)) => {
+                        let etag = format!("\"{}\"", contents.len());
+                        if if_none_match.as_ref() == Some(&etag) {
+                            ("304 Not Modified", Some(etag), "")
+                        } else {
+                            ("200 OK", Some(etag), *contents)
+                        }
+                    }
+                    None => ("404 Not Found", None, ""),
+                };
+
+                server_log
+                    .lock()
+                    .unwrap()
+                    .push((path, status.starts_with("304")));
+
+                let etag = etag.map(|etag| format!("ETag: {}\r\n", etag));
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    etag.unwrap_or_default(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        (Url::parse(&url).unwrap(), log)
+    }
+
+    #[test]
+    fn fetches_only_needed_files() {
+        let (url, log) = serve(vec![("biff/minimal", MINIMAL)]);
+        let cache = tempfile::tempdir().unwrap();
+        let registry = SparseRegistry::new(url, cache.path().to_owned());
+
+        let req: PackageReq = "biff/minimal@0.1.0".parse().unwrap();
+        assert_eq!(registry.query(&req).unwrap().len(), 1);
+        assert_eq!(registry.query(&req).unwrap().len(), 1);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![("biff/minimal".to_owned(), false)]
+        );
+
+        // After an update the cached file is revalidated instead of fetched
+        // again.
+        registry.update().unwrap();
+        assert_eq!(registry.query(&req).unwrap().len(), 1);
+        assert_eq!(log.lock().unwrap()[1], ("biff/minimal".to_owned(), true));
+    }
+
+    #[test]
+    fn missing_package() {
+        let (url, _log) = serve(vec![]);
+        let cache = tempfile::tempdir().unwrap();
+        let registry = SparseRegistry::new(url, cache.path().to_owned());
+
+        let req: PackageReq = "biff/missing@0.1.0".parse().unwrap();
+        let err = registry.query(&req).unwrap_err().to_string();
+        assert!(
+            err.contains("could not find package biff/missing"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn deprecations() {
+        let (url, _log) = serve(vec![("biff/deprecations.json", DEPRECATIONS)]);
+        let cache = tempfile::tempdir().unwrap();
+        let registry = SparseRegistry::new(url, cache.path().to_owned());
+
+        let deprecated: PackageName = "biff/exported-types".parse().unwrap();
+        let deprecation = registry.deprecation(&deprecated).unwrap().unwrap();
+        assert_eq!(deprecation.message, "No longer maintained");
+
+        let fine: PackageName = "biff/minimal".parse().unwrap();
+        assert_eq!(registry.deprecation(&fine).unwrap(), None);
+    }
+
+    #[test]
+    fn spec_urls() {
+        assert_eq!(
+            parse_spec("sparse+https://example.com/v1/index")
+                .unwrap()
+                .as_str(),
+            "https://example.com/v1/index/"
+        );
+        assert!(parse_spec("https://example.com/index").is_err());
+    }
+}
@@ -6,7 +6,8 @@ use fs_err::File;
 
 use crate::manifest::Manifest;
 use crate::package_id::PackageId;
-use crate::package_index::PackageIndexConfig;
+use crate::package_index::{read_deprecation, Deprecation, PackageIndexConfig};
+use crate::package_name::PackageName;
 use crate::package_req::PackageReq;
 use crate::package_source::PackageContents;
 
@@ -95,4 +96,8 @@ impl PackageSourceProvider for TestRegistry {
 
         Ok(sources)
     }
+
+    fn deprecation(&self, name: &PackageName) -> anyhow::Result<Option<Deprecation>> {
+        read_deprecation(&self.path.join("index"), name)
+    }
 }
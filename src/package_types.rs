@@ -0,0 +1,425 @@
+//! Finds the Luau types exported by a package so that the link files generated
+//! during installation can re-export them. Without this, tools like luau-lsp
+//! lose every exported type once a package is required through a link.
+
+use std::path::{Path, PathBuf};
+
+use fs_err::File;
+
+/// A single `export type` declaration from a package's entry module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeExport {
+    /// The name of the exported type.
+    ///
+    /// Example: `Promise`
+    pub name: String,
+
+    /// The generic parameters of the type, as they were declared.
+    ///
+    /// Example: `["T", "U = string", "V..."]`
+    pub generics: Vec<String>,
+}
+
+impl TypeExport {
+    /// Produces a declaration re-exporting this type from `module`, which is
+    /// the name of a local holding the required package.
+    ///
+    /// Example: `export type Map<K, V = string> = MODULE.Map<K, V>`
+    pub fn reexport(&self, module: &str) -> String {
+        if self.generics.is_empty() {
+            return format!("export type {name} = {module}.{name}", name = self.name);
+        }
+
+        // Defaults are only allowed in the declaration; the right hand side
+        // just passes each parameter through.
+        let arguments: Vec<&str> = self
+            .generics
+            .iter()
+            .map(|generic| generic.split('=').next().unwrap().trim())
+            .collect();
+
+        format!(
+            "export type {name}<{declared}> = {module}.{name}<{arguments}>",
+            name = self.name,
+            declared = self.generics.join(", "),
+            arguments = arguments.join(", "),
+        )
+    }
+}
+
+/// Read the types exported by the entry module of an installed package. Type
+/// exports are a convenience, so any problem finding or reading the entry
+/// module results in no exports rather than an error.
+pub fn read_type_exports(package_path: &Path) -> Vec<TypeExport> {
+    let entry_module = match find_entry_module(package_path) {
+        Some(path) => path,
+        None => {
+            log::debug!("No entry module found in {}", package_path.display());
+            return Vec::new();
+        }
+    };
+
+    match fs_err::read_to_string(&entry_module) {
+        Ok(source) => parse_type_exports(&source),
+        Err(err) => {
+            log::debug!("Could not read entry module for type exports: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// Locate the module that is returned when a package is required. This follows
+/// the `$path` of the package's `default.project.json` when it has one.
+pub fn find_entry_module(package_path: &Path) -> Option<PathBuf> {
+    let root = match File::open(package_path.join("default.project.json")) {
+        Ok(file) => {
+            let project: serde_json::Value = serde_json::from_reader(file).ok()?;
+            let tree_path = project.get("tree")?.get("$path")?.as_str()?;
+
+            package_path.join(tree_path)
+        }
+        Err(_) => package_path.to_owned(),
+    };
+
+    if root.is_file() {
+        return Some(root);
+    }
+
+    ["init.luau", "init.lua"]
+        .iter()
+        .map(|name| root.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Find every top-level `export type` declaration in some Luau source.
+pub fn parse_type_exports(source: &str) -> Vec<TypeExport> {
+    let masked = mask_comments_and_strings(source);
+    let line_starts =
+        std::iter::once(0).chain(masked.match_indices('\n').map(|(index, _)| index + 1));
+    let mut exports: Vec<TypeExport> = Vec::new();
+
+    for line_start in line_starts {
+        if let Some(export) = parse_declaration(&masked, source, line_start) {
+            if !exports.iter().any(|existing| existing.name == export.name) {
+                exports.push(export);
+            }
+        }
+    }
+
+    exports
+}
+
+/// Parse an `export type` declaration starting at `start`, if there is one.
+/// The declaration is found in `masked`, but its generics are copied from the
+/// same span of `source` so that any strings in them are kept as written.
+fn parse_declaration(masked: &str, source: &str, start: usize) -> Option<TypeExport> {
+    let rest = masked[start..].trim_start_matches([' ', '\t']);
+    let rest = strip_keyword(rest, "export")?;
+    let rest = strip_keyword(rest, "type")?;
+
+    let name_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let name = &rest[..name_len];
+
+    // Type functions are not types and can't be re-exported.
+    if name.is_empty() || name == "function" || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut rest = rest[name_len..].trim_start();
+    let mut generics = Vec::new();
+
+    if rest.starts_with('<') {
+        let end = matching_bracket(rest)?;
+        let open = masked.len() - rest.len();
+        let span = open + 1..open + end;
+
+        generics = split_top_level(&masked[span.clone()], &source[span]);
+        rest = rest[end + 1..].trim_start();
+    }
+
+    if !rest.starts_with('=') {
+        return None;
+    }
+
+    Some(TypeExport {
+        name: name.to_owned(),
+        generics,
+    })
+}
+
+fn strip_keyword<'a>(input: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = input.strip_prefix(keyword)?;
+
+    if rest.starts_with(char::is_whitespace) {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// Given a string starting with `<`, find the index of its matching `>`.
+/// Arrows (`->`) in function types are not treated as brackets.
+fn matching_bracket(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut previous = ' ';
+
+    for (index, char) in input.char_indices() {
+        match char {
+            '<' | '(' | '{' | '[' => depth += 1,
+            '>' if previous == '-' => {}
+            '>' | ')' | '}' | ']' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+
+        previous = char;
+    }
+
+    None
+}
+
+/// Split a list of generic parameters on the commas that aren't nested inside
+/// another type. `masked` and `source` are the same span of the masked and
+/// original source.
+fn split_top_level(masked: &str, source: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut previous = ' ';
+    let mut part_start = 0;
+
+    for (index, char) in masked.char_indices() {
+        match char {
+            '<' | '(' | '{' | '[' => depth += 1,
+            '>' if previous == '-' => {}
+            '>' | ')' | '}' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(normalize_whitespace(
+                    &masked[part_start..index],
+                    &source[part_start..index],
+                ));
+                part_start = index + 1;
+            }
+            _ => {}
+        }
+
+        previous = char;
+    }
+
+    if !masked[part_start..].trim().is_empty() {
+        parts.push(normalize_whitespace(
+            &masked[part_start..],
+            &source[part_start..],
+        ));
+    }
+
+    parts
+}
+
+/// Collapse the whitespace and comments in some source into single spaces,
+/// leaving strings untouched.
+fn normalize_whitespace(masked: &str, source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut pending_space = false;
+
+    for (index, char) in source.char_indices() {
+        if masked.as_bytes()[index].is_ascii_whitespace() {
+            pending_space = true;
+            continue;
+        }
+
+        if pending_space && !output.is_empty() {
+            output.push(' ');
+        }
+
+        pending_space = false;
+        output.push(char);
+    }
+
+    output
+}
+
+/// Produce a copy of Luau source with the same length, where comments are
+/// replaced by spaces and the contents of strings by underscores. Newlines in
+/// comments are kept so that lines still start in the same places.
+///
+/// This keeps declarations that are commented out or part of a string from
+/// being picked up, while every position still lines up with the original.
+fn mask_comments_and_strings(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(char) = rest.chars().next() {
+        if rest.starts_with("--") {
+            let after_dashes = &rest[2..];
+            let after = match long_bracket_level(after_dashes) {
+                Some(level) => skip_long_bracket(after_dashes, level),
+                None => after_dashes
+                    .find('\n')
+                    .map_or("", |end| &after_dashes[end..]),
+            };
+
+            let comment = &rest[..rest.len() - after.len()];
+            output.extend(comment.bytes().map(|byte| match byte {
+                b'\n' => '\n',
+                _ => ' ',
+            }));
+            rest = after;
+            continue;
+        }
+
+        let string_len = match long_bracket_level(rest) {
+            Some(level) => Some(rest.len() - skip_long_bracket(rest, level).len()),
+            None if char == '"' || char == '\'' || char == '`' => Some(quoted_string_len(rest)),
+            None => None,
+        };
+
+        if let Some(len) = string_len {
+            output.extend(rest[..len].bytes().map(|_| '_'));
+            rest = &rest[len..];
+            continue;
+        }
+
+        output.push(char);
+        rest = &rest[char.len_utf8()..];
+    }
+
+    output
+}
+
+/// The length of the quoted string at the start of `input`, including its
+/// quotes. Unterminated strings end at the end of the line.
+fn quoted_string_len(input: &str) -> usize {
+    let quote = input.chars().next().unwrap();
+    let mut escaped = false;
+
+    for (index, next) in input.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if next == '\\' {
+            escaped = true;
+        } else if next == quote {
+            return index + next.len_utf8();
+        } else if next == '\n' {
+            return index;
+        }
+    }
+
+    input.len()
+}
+
+/// If `input` starts with a long bracket like `[[` or `[==[`, returns its
+/// level (the number of `=` signs).
+fn long_bracket_level(input: &str) -> Option<usize> {
+    let rest = input.strip_prefix('[')?;
+    let level = rest.chars().take_while(|&c| c == '=').count();
+
+    if rest[level..].starts_with('[') {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+/// Skip past a long bracket of the given level, returning what follows it.
+fn skip_long_bracket(input: &str, level: usize) -> &str {
+    let close = format!("]{}]", "=".repeat(level));
+    let body = &input[level + 2..];
+
+    match body.find(&close) {
+        Some(end) => &body[end + close.len()..],
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn export(name: &str, generics: &[&str]) -> TypeExport {
+        TypeExport {
+            name: name.to_owned(),
+            generics: generics.iter().map(|g| g.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn simple_exports() {
+        let source = "export type Foo = number\nlocal x = 5\n  export type Bar = { x: number }\n";
+        assert_eq!(
+            parse_type_exports(source),
+            vec![export("Foo", &[]), export("Bar", &[])]
+        );
+    }
+
+    #[test]
+    fn generics() {
+        let source = indoc::indoc! {r#"
+            export type Map<K, V = string> = { [K]: V }
+            export type Callback<T... = ...any> = (T...) -> ()
+            export type Nested<T = Map<string, () -> number>,
+                U> = T
+        "#};
+
+        assert_eq!(
+            parse_type_exports(source),
+            vec![
+                export("Map", &["K", "V = string"]),
+                export("Callback", &["T... = ...any"]),
+                export("Nested", &["T = Map<string, () -> number>", "U"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_comments_and_strings() {
+        let source = indoc::indoc! {r#"
+            -- export type Commented = number
+            --[[
+            export type Block = number
+            ]]
+            local s = [[
+            export type InString = number
+            ]]
+            export type Real = number
+        "#};
+
+        assert_eq!(parse_type_exports(source), vec![export("Real", &[])]);
+    }
+
+    #[test]
+    fn keeps_strings_in_generics() {
+        let source = indoc::indoc! {r#"
+            export type Mode<T = "a" | "b", U = 'x -- y'> = T
+        "#};
+
+        assert_eq!(
+            parse_type_exports(source),
+            vec![export("Mode", &[r#"T = "a" | "b""#, "U = 'x -- y'"])]
+        );
+    }
+
+    #[test]
+    fn ignores_locals_and_functions() {
+        let source = "type Local = number\nexport type function Fn(t)\n\treturn t\nend\n";
+        assert!(parse_type_exports(source).is_empty());
+    }
+
+    #[test]
+    fn reexport() {
+        assert_eq!(
+            export("Foo", &[]).reexport("MODULE"),
+            "export type Foo = MODULE.Foo"
+        );
+        assert_eq!(
+            export("Map", &["K", "V = string", "T..."]).reexport("MODULE"),
+            "export type Map<K, V = string, T...> = MODULE.Map<K, V, T...>"
+        );
+    }
+}
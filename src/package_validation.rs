@@ -0,0 +1,353 @@
+//! Checks that a package is well formed before it is packed or published, so
+//! that mistakes are caught locally instead of by the registry or by the
+//! people depending on the package.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::bail;
+use ubyte::{ByteUnit, ToByteUnit};
+
+use crate::manifest::{Manifest, Realm};
+use crate::package_contents::PackageContents;
+use crate::package_req::PackageReq;
+
+/// The largest package archive that registries will accept.
+pub const MAX_PACKAGE_SIZE: ByteUnit = ByteUnit::Mebibyte(2);
+
+const LUAU_KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in", "local",
+    "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Check the manifest and project layout of the package at `project_path`.
+/// Every problem found is reported at once.
+pub fn validate_package(project_path: &Path, manifest: &Manifest) -> anyhow::Result<()> {
+    let mut problems = Vec::new();
+
+    check_entry_point(project_path, &mut problems);
+    check_license(manifest, &mut problems);
+
+    let sections = [
+        ("dependencies", &manifest.dependencies),
+        ("server-dependencies", &manifest.server_dependencies),
+        ("client-dependencies", &manifest.client_dependencies),
+        ("dev-dependencies", &manifest.dev_dependencies),
+    ];
+
+    for (section, dependencies) in sections.iter() {
+        check_aliases(section, dependencies, &mut problems);
+    }
+
+    report_problems(manifest, &problems)
+}
+
+/// Check that every dependency is from a realm that the section it's declared
+/// in can depend on, the same way the resolver does: some version matching the
+/// requirement has to be. `query` finds the versions of a dependency, and
+/// dependencies it can't find are left for the resolver to report.
+pub fn validate_dependency_realms(
+    manifest: &Manifest,
+    query: impl Fn(&PackageReq) -> anyhow::Result<Vec<Manifest>>,
+) -> anyhow::Result<()> {
+    let mut problems = Vec::new();
+
+    // Dev dependencies are never installed for the package's dependents, so
+    // they can be from any realm.
+    let sections = [
+        ("dependencies", Realm::Shared, &manifest.dependencies),
+        (
+            "server-dependencies",
+            Realm::Server,
+            &manifest.server_dependencies,
+        ),
+        (
+            "client-dependencies",
+            Realm::Client,
+            &manifest.client_dependencies,
+        ),
+    ];
+
+    for (section, realm, dependencies) in sections.iter() {
+        for (alias, package_req) in dependencies.iter() {
+            let candidates = match query(package_req) {
+                Ok(candidates) if !candidates.is_empty() => candidates,
+                _ => continue,
+            };
+
+            let valid = candidates
+                .iter()
+                .any(|candidate| Realm::is_dependency_valid(*realm, candidate.package.realm));
+
+            if !valid {
+                problems.push(format!(
+                    "`{}` in {} is a {:?} package, which {:?} dependencies cannot require",
+                    alias, section, candidates[0].package.realm, realm
+                ));
+            }
+        }
+    }
+
+    report_problems(manifest, &problems)
+}
+
+fn report_problems(manifest: &Manifest, problems: &[String]) -> anyhow::Result<()> {
+    if !problems.is_empty() {
+        bail!(
+            "Package {} is not valid:\n{}",
+            manifest.package_id(),
+            problems
+                .iter()
+                .map(|problem| format!("  - {}", problem))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+/// Check that a packed archive can be uploaded.
+pub fn validate_archive(contents: &PackageContents) -> anyhow::Result<()> {
+    let size = contents.data().len().bytes();
+
+    if size > MAX_PACKAGE_SIZE {
+        bail!(
+            "Package size ({}) exceeds {}. Reduce package size and try again.",
+            size,
+            MAX_PACKAGE_SIZE
+        );
+    }
+
+    Ok(())
+}
+
+fn check_entry_point(project_path: &Path, problems: &mut Vec<String>) {
+    let project_file = project_path.join("default.project.json");
+
+    if !project_file.is_file() {
+        let has_init = ["init.luau", "init.lua"]
+            .iter()
+            .any(|name| project_path.join(name).is_file());
+
+        if !has_init {
+            problems.push(
+                "no entry point was found; add a default.project.json or an init.lua".to_owned(),
+            );
+        }
+
+        return;
+    }
+
+    let project = match fs_err::read_to_string(&project_file)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| Ok(serde_json::from_str::<serde_json::Value>(&contents)?))
+    {
+        Ok(project) => project,
+        Err(err) => {
+            problems.push(format!("default.project.json could not be read: {}", err));
+            return;
+        }
+    };
+
+    if project.get("name").and_then(|name| name.as_str()).is_none() {
+        problems.push("default.project.json must have a `name`".to_owned());
+    }
+
+    let tree_path = project
+        .get("tree")
+        .and_then(|tree| tree.get("$path"))
+        .and_then(|tree_path| tree_path.as_str());
+
+    if let Some(tree_path) = tree_path {
+        if !project_path.join(tree_path).exists() {
+            problems.push(format!(
+                "the `$path` in default.project.json, `{}`, does not exist",
+                tree_path
+            ));
+        }
+    }
+}
+
+fn check_license(manifest: &Manifest, problems: &mut Vec<String>) {
+    if let Some(license) = &manifest.package.license {
+        if let Err(err) = spdx::Expression::parse(license) {
+            problems.push(format!(
+                "license `{}` is not a valid SPDX expression: {}",
+                license, err.reason
+            ));
+        }
+    }
+}
+
+fn check_aliases(
+    section: &str,
+    dependencies: &BTreeMap<String, PackageReq>,
+    problems: &mut Vec<String>,
+) {
+    for alias in dependencies.keys() {
+        if !is_luau_identifier(alias) {
+            problems.push(format!(
+                "dependency alias `{}` in {} must be a valid Luau identifier",
+                alias, section
+            ));
+        }
+    }
+}
+
+fn is_luau_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    let starts_well = match chars.next() {
+        Some(first) => first.is_ascii_alphabetic() || first == '_',
+        None => false,
+    };
+
+    starts_well
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !LUAU_KEYWORDS.contains(&name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    use crate::test_package::PackageBuilder;
+
+    fn manifest(source: &str) -> Manifest {
+        Manifest::from_slice(source.as_bytes()).unwrap()
+    }
+
+    const VALID: &str = r#"
+        [package]
+        name = "biff/valid"
+        version = "0.1.0"
+        license = "MIT OR Apache-2.0"
+        realm = "shared"
+        registry = "test-registries/primary-registry"
+
+        [dependencies]
+        Minimal = "biff/minimal@0.1.0"
+    "#;
+
+    #[test]
+    fn identifiers() {
+        assert!(is_luau_identifier("Roact"));
+        assert!(is_luau_identifier("_private2"));
+        assert!(!is_luau_identifier("2fast"));
+        assert!(!is_luau_identifier("has-dash"));
+        assert!(!is_luau_identifier("end"));
+        assert!(!is_luau_identifier(""));
+    }
+
+    #[test]
+    fn valid_package() {
+        let project = tempdir().unwrap();
+        fs_err::write(project.path().join("init.lua"), "return nil").unwrap();
+
+        validate_package(project.path(), &manifest(VALID)).unwrap();
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let project = tempdir().unwrap();
+        let manifest = manifest(
+            r#"
+            [package]
+            name = "biff/invalid"
+            version = "0.1.0"
+            license = "Not A License"
+            realm = "shared"
+            registry = "test-registries/primary-registry"
+
+            [dependencies]
+            bad-alias = "biff/minimal@0.1.0"
+            "#,
+        );
+
+        let err = validate_package(project.path(), &manifest)
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("no entry point"), "{}", err);
+        assert!(err.contains("not a valid SPDX expression"), "{}", err);
+        assert!(err.contains("`bad-alias`"), "{}", err);
+    }
+
+    #[test]
+    fn dependency_realms() {
+        let query = |package_req: &PackageReq| {
+            let realm = match package_req.name().name() {
+                "server" => Realm::Server,
+                "client" => Realm::Client,
+                _ => Realm::Shared,
+            };
+
+            let package = PackageBuilder::new(format!("{}@0.1.0", package_req.name()))
+                .with_realm(realm)
+                .into_manifest();
+
+            Ok::<_, anyhow::Error>(vec![package])
+        };
+
+        // Shared packages can depend on server packages from their
+        // server-dependencies, like the resolver allows.
+        let valid = manifest(
+            r#"
+            [package]
+            name = "biff/valid"
+            version = "0.1.0"
+            realm = "shared"
+            registry = "test-registries/primary-registry"
+
+            [dependencies]
+            Minimal = "biff/minimal@0.1.0"
+
+            [server-dependencies]
+            Server = "biff/server@0.1.0"
+            "#,
+        );
+        validate_dependency_realms(&valid, query).unwrap();
+
+        let invalid = manifest(
+            r#"
+            [package]
+            name = "biff/invalid"
+            version = "0.1.0"
+            realm = "shared"
+            registry = "test-registries/primary-registry"
+
+            [dependencies]
+            Server = "biff/server@0.1.0"
+
+            [server-dependencies]
+            Client = "biff/client@0.1.0"
+            "#,
+        );
+
+        let err = validate_dependency_realms(&invalid, query)
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("`Server` in dependencies"), "{}", err);
+        assert!(err.contains("`Client` in server-dependencies"), "{}", err);
+    }
+
+    #[test]
+    fn malformed_project_file() {
+        let project = tempdir().unwrap();
+        fs_err::write(
+            project.path().join("default.project.json"),
+            "{ \"tree\": {} }",
+        )
+        .unwrap();
+
+        let err = validate_package(project.path(), &manifest(VALID))
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("must have a `name`"), "{}", err);
+    }
+}
@@ -8,6 +8,7 @@ use serde::Serialize;
 
 use crate::manifest::{Manifest, Realm};
 use crate::package_id::PackageId;
+use crate::package_index::Deprecation;
 use crate::package_req::PackageReq;
 use crate::package_source::{PackageSourceId, PackageSourceMap, PackageSourceProvider};
 
@@ -31,6 +32,9 @@ pub struct Resolve {
     /// Graph of all dependencies originating from the "server" dependency realm.
     pub server_dependencies: BTreeMap<PackageId, BTreeMap<String, PackageId>>,
 
+    /// Graph of all dependencies originating from the "client" dependency realm.
+    pub client_dependencies: BTreeMap<PackageId, BTreeMap<String, PackageId>>,
+
     /// Graph of all dependencies originating from the "dev" dependency realm.
     pub dev_dependencies: BTreeMap<PackageId, BTreeMap<String, PackageId>>,
 }
@@ -42,16 +46,109 @@ impl Resolve {
         let dependencies = match dep_realm {
             Realm::Shared => self.shared_dependencies.entry(source).or_default(),
             Realm::Server => self.server_dependencies.entry(source).or_default(),
+            Realm::Client => self.client_dependencies.entry(source).or_default(),
             Realm::Dev => self.dev_dependencies.entry(source).or_default(),
         };
         dependencies.insert(dep_name, dep);
     }
+
+    /// Widen the origin realm of an activated package so that it can also be
+    /// required from `realm`, returning its new origin realm.
+    ///
+    /// Everything the package depends on has to be reachable from wherever the
+    /// package ends up, so the packages it already activated are widened along
+    /// with it, as are its requests that are still waiting to be resolved.
+    fn widen_origin_realm(
+        &mut self,
+        package_id: &PackageId,
+        realm: Realm,
+        packages_to_visit: &mut VecDeque<DependencyRequest>,
+    ) -> Realm {
+        let mut to_widen = vec![(package_id.clone(), realm)];
+
+        while let Some((package_id, realm)) = to_widen.pop() {
+            let metadata = self
+                .metadata
+                .get_mut(&package_id)
+                .expect("activated package was missing metadata");
+
+            let widened = widest_origin_realm(metadata.origin_realm, realm);
+            if widened == metadata.origin_realm {
+                continue;
+            }
+            metadata.origin_realm = widened;
+
+            for request in packages_to_visit.iter_mut() {
+                if request.request_source == package_id {
+                    request.origin_realm = widest_origin_realm(request.origin_realm, widened);
+                }
+            }
+
+            let dependencies = self
+                .shared_dependencies
+                .get(&package_id)
+                .into_iter()
+                .chain(self.server_dependencies.get(&package_id))
+                .chain(self.client_dependencies.get(&package_id))
+                .chain(self.dev_dependencies.get(&package_id))
+                .flat_map(|dependencies| dependencies.values());
+
+            for dependency in dependencies {
+                to_widen.push((dependency.clone(), widened));
+            }
+        }
+
+        self.metadata[package_id].origin_realm
+    }
+
+    /// Find every package in the graph that has been deprecated by its owners.
+    /// Deprecations are only informational, so a deprecation that can't be
+    /// read is skipped rather than failing the whole install.
+    pub fn deprecations(
+        &self,
+        package_sources: &PackageSourceMap,
+    ) -> Vec<(PackageId, Deprecation)> {
+        // The root project is the only activated package that nothing depends
+        // on, and it isn't from a registry.
+        let dependencies: BTreeSet<&PackageId> = self
+            .shared_dependencies
+            .values()
+            .chain(self.server_dependencies.values())
+            .chain(self.client_dependencies.values())
+            .chain(self.dev_dependencies.values())
+            .flat_map(|dependencies| dependencies.values())
+            .collect();
+
+        let mut deprecations = Vec::new();
+
+        for package_id in dependencies {
+            let source = self
+                .metadata
+                .get(package_id)
+                .and_then(|metadata| package_sources.get(&metadata.source_registry));
+
+            let source = match source {
+                Some(source) => source,
+                None => continue,
+            };
+
+            match source.deprecation(package_id.name()) {
+                Ok(Some(deprecation)) => deprecations.push((package_id.clone(), deprecation)),
+                Ok(None) => {}
+                Err(err) => {
+                    log::debug!("Could not check if {} is deprecated: {:#}", package_id, err)
+                }
+            }
+        }
+
+        deprecations
+    }
 }
 
 /// A single node in the package resolution graph.
 /// Origin realm is the "most restrictive" realm the package can still be dependended
 /// upon. It is where the package gets placed during install.
-/// See [ origin_realm clarification ]. In the resolve function for more info.
+/// See [ origin_realm clarification ]. In the widest_origin_realm function for more info.
 #[derive(Debug, Serialize, Clone)]
 pub struct ResolvePackageMetadata {
     pub realm: Realm,
@@ -101,6 +198,16 @@ pub fn resolve(
         });
     }
 
+    for (alias, req) in &root_manifest.client_dependencies {
+        packages_to_visit.push_back(DependencyRequest {
+            request_source: root_manifest.package_id(),
+            request_realm: Realm::Client,
+            origin_realm: Realm::Client,
+            package_alias: alias.clone(),
+            package_req: req.clone(),
+        });
+    }
+
     for (alias, req) in &root_manifest.dev_dependencies {
         packages_to_visit.push_back(DependencyRequest {
             request_source: root_manifest.package_id(),
@@ -130,28 +237,11 @@ pub fn resolve(
         // our constraints.
         for package_id in &matching_activated {
             if dependency_request.package_req.matches_id(package_id) {
-                let metadata = resolve
-                    .metadata
-                    .get_mut(package_id)
-                    .expect("activated package was missing metadata");
-
-                // [ origin_realm clarification ]
-                // We want to set the origin to the most restrictive origin possible.
-                // For example we want to keep packages in the dev realm unless a dependency
-                // with a shared/server origin requires it. This way server/shared dependencies
-                // which only originate from dev dependencies get put into the dev folder even
-                // if they usually belong to another realm. Likewise we want to keep shared
-                // dependencies in the server realm unless they are explicitly required as a
-                // shared dependency.
-                let realm_match = match (metadata.origin_realm, dependency_request.origin_realm) {
-                    (_, Realm::Shared) => Realm::Shared,
-                    (Realm::Shared, _) => Realm::Shared,
-                    (_, Realm::Server) => Realm::Server,
-                    (Realm::Server, _) => Realm::Server,
-                    (Realm::Dev, Realm::Dev) => Realm::Dev,
-                };
-
-                metadata.origin_realm = realm_match;
+                let realm_match = resolve.widen_origin_realm(
+                    package_id,
+                    dependency_request.origin_realm,
+                    &mut packages_to_visit,
+                );
 
                 resolve.activate(
                     dependency_request.request_source.clone(),
@@ -268,6 +358,16 @@ pub fn resolve(
                 })
             }
 
+            for (alias, req) in &candidate.client_dependencies {
+                packages_to_visit.push_back(DependencyRequest {
+                    request_source: candidate_id.clone(),
+                    request_realm: Realm::Client,
+                    origin_realm: dependency_request.origin_realm,
+                    package_alias: alias.clone(),
+                    package_req: req.clone(),
+                })
+            }
+
             continue 'outer;
         }
 
@@ -298,6 +398,30 @@ pub fn resolve(
     Ok(resolve)
 }
 
+/// Combine the origin realm a package already has with one it's also required
+/// from.
+fn widest_origin_realm(current: Realm, requested: Realm) -> Realm {
+    // [ origin_realm clarification ]
+    // We want to set the origin to the most restrictive origin possible.
+    // For example we want to keep packages in the dev realm unless a dependency
+    // with a shared/server origin requires it. This way server/shared dependencies
+    // which only originate from dev dependencies get put into the dev folder even
+    // if they usually belong to another realm. Likewise we want to keep shared
+    // dependencies in the server or client realm unless they are explicitly required
+    // as a shared dependency, or are required from both the server and the client.
+    match (current, requested) {
+        (_, Realm::Shared) => Realm::Shared,
+        (Realm::Shared, _) => Realm::Shared,
+        (Realm::Server, Realm::Client) => Realm::Shared,
+        (Realm::Client, Realm::Server) => Realm::Shared,
+        (_, Realm::Server) => Realm::Server,
+        (Realm::Server, _) => Realm::Server,
+        (_, Realm::Client) => Realm::Client,
+        (Realm::Client, _) => Realm::Client,
+        (Realm::Dev, Realm::Dev) => Realm::Dev,
+    }
+}
+
 fn compatible(a: &Version, b: &Version) -> bool {
     if a == b {
         return true;
@@ -434,6 +558,85 @@ mod tests {
         test_project(registry, root)
     }
 
+    /// Client dependencies are placed in the client realm, along with the
+    /// shared dependencies that only they use.
+    #[test]
+    fn client_to_shared() -> anyhow::Result<()> {
+        let registry = InMemoryRegistry::new();
+        registry.publish(PackageBuilder::new("biff/shared@1.0.0"));
+        registry.publish(
+            PackageBuilder::new("biff/client@1.0.0")
+                .with_realm(Realm::Client)
+                .with_dep("Shared", "biff/shared@1.0.0"),
+        );
+
+        let root =
+            PackageBuilder::new("biff/root@1.0.0").with_client_dep("Client", "biff/client@1.0.0");
+
+        test_project(registry, root)
+    }
+
+    /// A shared dependency that is used by both server and client dependencies
+    /// has to be visible to both, so it is placed in the shared realm.
+    #[test]
+    fn server_and_client_to_shared() -> anyhow::Result<()> {
+        let registry = InMemoryRegistry::new();
+        registry.publish(PackageBuilder::new("biff/shared@1.0.0"));
+        registry.publish(
+            PackageBuilder::new("biff/server@1.0.0")
+                .with_realm(Realm::Server)
+                .with_dep("Shared", "biff/shared@1.0.0"),
+        );
+        registry.publish(
+            PackageBuilder::new("biff/client@1.0.0")
+                .with_realm(Realm::Client)
+                .with_dep("Shared", "biff/shared@1.0.0"),
+        );
+
+        let root = PackageBuilder::new("biff/root@1.0.0")
+            .with_server_dep("Server", "biff/server@1.0.0")
+            .with_client_dep("Client", "biff/client@1.0.0");
+
+        test_project(registry, root)
+    }
+
+    #[test]
+    fn server_and_client_to_shared_transitive() -> anyhow::Result<()> {
+        let registry = InMemoryRegistry::new();
+        registry.publish(PackageBuilder::new("biff/bottom@1.0.0"));
+        registry.publish(
+            PackageBuilder::new("biff/leaf@1.0.0").with_dep("Bottom", "biff/bottom@1.0.0"),
+        );
+        registry
+            .publish(PackageBuilder::new("biff/middle@1.0.0").with_dep("Leaf", "biff/leaf@1.0.0"));
+        registry.publish(
+            PackageBuilder::new("biff/client@1.0.0")
+                .with_realm(Realm::Client)
+                .with_dep("Middle", "biff/middle@1.0.0"),
+        );
+
+        // The middle package is first reached from the server, and only later
+        // from the client once its own dependencies are already activated.
+        let root = PackageBuilder::new("biff/root@1.0.0")
+            .with_server_dep("Middle", "biff/middle@1.0.0")
+            .with_client_dep("Client", "biff/client@1.0.0");
+
+        test_project(registry, root)
+    }
+
+    #[test]
+    fn fail_client_in_server() {
+        let registry = InMemoryRegistry::new();
+        registry.publish(PackageBuilder::new("biff/client@1.0.0").with_realm(Realm::Client));
+
+        let root =
+            PackageBuilder::new("biff/root@1.0.0").with_server_dep("Client", "biff/client@1.0.0");
+
+        let package_sources = PackageSourceMap::new(Box::new(registry.source()));
+        let err = resolve(root.manifest(), &Default::default(), &package_sources).unwrap_err();
+        insta::assert_display_snapshot!(err);
+    }
+
     #[test]
     fn fail_server_in_shared() {
         let registry = InMemoryRegistry::new();
@@ -0,0 +1,316 @@
+//! Generates entries in a Rojo sourcemap (`sourcemap.json`) for installed
+//! packages, so that tools like luau-lsp can follow requires through the
+//! package folders without having to run Rojo after every install.
+
+use std::path::{Component, Path};
+
+use anyhow::{bail, Context};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+pub const SOURCEMAP_FILE_NAME: &str = "sourcemap.json";
+
+/// A single instance in a Rojo sourcemap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourcemapNode {
+    pub name: String,
+    pub class_name: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_paths: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SourcemapNode>,
+}
+
+impl SourcemapNode {
+    pub fn new(name: impl Into<String>, class_name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            class_name: class_name.into(),
+            file_paths: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Load an existing sourcemap, if there is one at the given path.
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = fs::read_to_string(path)?;
+        let sourcemap = serde_json::from_str(&contents)
+            .with_context(|| format!("Malformed sourcemap at {}", path.display()))?;
+
+        Ok(Some(sourcemap))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut contents = serde_json::to_string_pretty(self)?;
+        contents.push('\n');
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    /// Build the node for an installed package folder like `Packages`,
+    /// following the same rules Rojo uses to turn files into instances. Like
+    /// Rojo's sourcemaps, only scripts and the instances containing them are
+    /// included.
+    ///
+    /// File paths are written relative to `project_path`.
+    pub fn from_package_folder(
+        project_path: &Path,
+        folder: &Path,
+        name: &str,
+    ) -> anyhow::Result<Self> {
+        let mut node = from_directory(project_path, folder)?
+            .unwrap_or_else(|| SourcemapNode::new(name, "Folder"));
+        node.name = name.to_owned();
+
+        Ok(node)
+    }
+
+    /// Place `node` at the given DataModel path, like
+    /// `game.ReplicatedStorage.Packages`, replacing whatever was there before.
+    /// Missing ancestors are created as services or folders.
+    pub fn insert(&mut self, datamodel_path: &str, mut node: SourcemapNode) -> anyhow::Result<()> {
+        let segments = parse_datamodel_path(datamodel_path)?;
+        let (last, ancestors) = segments.split_last().unwrap();
+
+        let mut parent = self;
+        for (depth, segment) in ancestors.iter().enumerate() {
+            let index = match parent.children.iter().position(|c| c.name == *segment) {
+                Some(index) => index,
+                None => {
+                    // Direct children of the DataModel are services, whose
+                    // class names match their names, as do the script
+                    // containers inside StarterPlayer.
+                    let is_player_scripts = depth == 1
+                        && ancestors[0] == "StarterPlayer"
+                        && segment.starts_with("Starter");
+                    let class_name = if depth == 0 || is_player_scripts {
+                        *segment
+                    } else {
+                        "Folder"
+                    };
+                    parent
+                        .children
+                        .push(SourcemapNode::new(*segment, class_name));
+                    parent.children.len() - 1
+                }
+            };
+
+            parent = &mut parent.children[index];
+        }
+
+        node.name = (*last).to_owned();
+
+        match parent.children.iter_mut().find(|c| c.name == node.name) {
+            Some(existing) => *existing = node,
+            None => parent.children.push(node),
+        }
+
+        Ok(())
+    }
+}
+
+/// Split a DataModel path like `game.ReplicatedStorage.Packages` into the
+/// names of the instances below the DataModel.
+fn parse_datamodel_path(path: &str) -> anyhow::Result<Vec<&str>> {
+    let mut segments = path.split('.');
+
+    if segments.next() != Some("game") {
+        bail!("Package location `{}` must start with `game.`", path);
+    }
+
+    let segments: Vec<&str> = segments.collect();
+    if segments.is_empty() || segments.iter().any(|segment| segment.is_empty()) {
+        bail!("Package location `{}` is not a valid DataModel path", path);
+    }
+
+    Ok(segments)
+}
+
+fn from_path(project_path: &Path, path: &Path) -> anyhow::Result<Option<SourcemapNode>> {
+    if path.is_dir() {
+        from_directory(project_path, path)
+    } else {
+        Ok(from_file(project_path, path))
+    }
+}
+
+fn from_directory(project_path: &Path, path: &Path) -> anyhow::Result<Option<SourcemapNode>> {
+    let name = file_name(path);
+
+    // Packages are usually Rojo projects themselves, which point at the
+    // directory that actually holds their code.
+    let project_file = path.join("default.project.json");
+    if project_file.is_file() {
+        let contents = fs::read_to_string(&project_file)?;
+        let project: serde_json::Value = serde_json::from_str(&contents)
+            .with_context(|| format!("Malformed Rojo project at {}", project_file.display()))?;
+
+        let tree_path = project
+            .get("tree")
+            .and_then(|tree| tree.get("$path"))
+            .and_then(|tree_path| tree_path.as_str());
+
+        let tree_path = match tree_path {
+            Some(tree_path) => tree_path,
+            None => return Ok(None),
+        };
+
+        let mut node = match from_path(project_path, &path.join(tree_path))? {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+
+        node.name = name;
+        node.file_paths
+            .insert(0, relative_path(project_path, &project_file));
+
+        return Ok(Some(node));
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    entries.sort();
+
+    let mut node = SourcemapNode::new(name, "Folder");
+
+    for entry in entries {
+        if let Some((script_name, class_name)) = script_kind(&entry) {
+            if script_name == "init" {
+                node.class_name = class_name.to_owned();
+                node.file_paths.push(relative_path(project_path, &entry));
+                continue;
+            }
+        }
+
+        if let Some(child) = from_path(project_path, &entry)? {
+            node.children.push(child);
+        }
+    }
+
+    if node.file_paths.is_empty() && node.children.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(node))
+}
+
+fn from_file(project_path: &Path, path: &Path) -> Option<SourcemapNode> {
+    let (name, class_name) = script_kind(path)?;
+
+    let mut node = SourcemapNode::new(name, class_name);
+    node.file_paths.push(relative_path(project_path, path));
+
+    Some(node)
+}
+
+/// If the file at `path` is a script, returns the name of the instance it
+/// becomes and its class name.
+fn script_kind(path: &Path) -> Option<(&str, &'static str)> {
+    let file_name = path.file_name()?.to_str()?;
+    let stem = file_name
+        .strip_suffix(".luau")
+        .or_else(|| file_name.strip_suffix(".lua"))?;
+
+    if let Some(name) = stem.strip_suffix(".server") {
+        Some((name, "Script"))
+    } else if let Some(name) = stem.strip_suffix(".client") {
+        Some((name, "LocalScript"))
+    } else {
+        Some((stem, "ModuleScript"))
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Sourcemaps use forward slashes on every platform.
+fn relative_path(project_path: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(project_path).unwrap_or(path);
+
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert_creates_ancestors() {
+        let mut root = SourcemapNode::new("game", "DataModel");
+        root.insert(
+            "game.ServerScriptService.Lib.Packages",
+            SourcemapNode::new("ignored", "Folder"),
+        )
+        .unwrap();
+
+        let service = &root.children[0];
+        assert_eq!(service.name, "ServerScriptService");
+        assert_eq!(service.class_name, "ServerScriptService");
+
+        let lib = &service.children[0];
+        assert_eq!(lib.class_name, "Folder");
+        assert_eq!(lib.children[0].name, "Packages");
+    }
+
+    #[test]
+    fn insert_replaces_existing() {
+        let mut root = SourcemapNode::new("game", "DataModel");
+        let mut old = SourcemapNode::new("Packages", "Folder");
+        old.children
+            .push(SourcemapNode::new("Stale", "ModuleScript"));
+
+        root.insert("game.ReplicatedStorage.Packages", old).unwrap();
+        root.insert(
+            "game.ReplicatedStorage.Packages",
+            SourcemapNode::new("Packages", "Folder"),
+        )
+        .unwrap();
+
+        let storage = &root.children[0];
+        assert_eq!(storage.children.len(), 1);
+        assert!(storage.children[0].children.is_empty());
+    }
+
+    #[test]
+    fn invalid_datamodel_paths() {
+        assert!(parse_datamodel_path("ReplicatedStorage.Packages").is_err());
+        assert!(parse_datamodel_path("game").is_err());
+        assert!(parse_datamodel_path("game..Packages").is_err());
+    }
+
+    #[test]
+    fn script_kinds() {
+        assert_eq!(
+            script_kind(Path::new("Foo.lua")),
+            Some(("Foo", "ModuleScript"))
+        );
+        assert_eq!(
+            script_kind(Path::new("Foo.server.luau")),
+            Some(("Foo", "Script"))
+        );
+        assert_eq!(
+            script_kind(Path::new("init.client.lua")),
+            Some(("init", "LocalScript"))
+        );
+        assert_eq!(script_kind(Path::new("wally.toml")), None);
+    }
+}
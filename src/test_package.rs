@@ -36,13 +36,17 @@ impl PackageBuilder {
                 description: None,
                 license: None,
                 authors: Vec::new(),
+                keywords: Vec::new(),
+                tags: Vec::new(),
                 include: Vec::new(),
                 exclude: Vec::new(),
                 private: false,
             },
             place: Default::default(),
+            install: Default::default(),
             dependencies: Default::default(),
             server_dependencies: Default::default(),
+            client_dependencies: Default::default(),
             dev_dependencies: Default::default(),
         };
 
@@ -57,6 +61,26 @@ impl PackageBuilder {
         self
     }
 
+    pub fn with_license<S: Into<String>>(mut self, license: S) -> Self {
+        self.manifest.package.license = Some(license.into());
+        self
+    }
+
+    pub fn with_author<S: Into<String>>(mut self, author: S) -> Self {
+        self.manifest.package.authors.push(author.into());
+        self
+    }
+
+    pub fn with_keyword<S: Into<String>>(mut self, keyword: S) -> Self {
+        self.manifest.package.keywords.push(keyword.into());
+        self
+    }
+
+    pub fn with_tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.manifest.package.tags.push(tag.into());
+        self
+    }
+
     pub fn with_dep<A, R>(mut self, alias: A, package_req: R) -> Self
     where
         A: Into<String>,
@@ -79,6 +103,17 @@ impl PackageBuilder {
         self
     }
 
+    pub fn with_client_dep<A, R>(mut self, alias: A, package_req: R) -> Self
+    where
+        A: Into<String>,
+        R: AsRef<str>,
+    {
+        let req: PackageReq = package_req.as_ref().parse().expect("invalid PackageReq");
+
+        self.manifest.client_dependencies.insert(alias.into(), req);
+        self
+    }
+
     pub fn with_file<P, C>(mut self, path: P, contents: C) -> Self
     where
         P: Into<String>,
@@ -0,0 +1,250 @@
+//! Finds the packages in a workspace, a directory holding several projects
+//! that are developed and published together, and works out the order they
+//! have to be published in.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use fs_err as fs;
+
+use crate::manifest::Manifest;
+use crate::package_compat;
+
+/// A single package in a workspace.
+#[derive(Debug, Clone)]
+pub struct WorkspaceMember {
+    pub path: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl WorkspaceMember {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let manifest = package_compat::load_backwards_compatible_package(path)
+            .with_context(|| format!("could not load workspace member at {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            manifest,
+        })
+    }
+}
+
+/// Find every package at or below `root`. Hidden directories and the folders
+/// a package installs its dependencies into are not searched, since the
+/// packages in them belong to someone else.
+pub fn find_members(root: &Path) -> anyhow::Result<Vec<WorkspaceMember>> {
+    let mut members = Vec::new();
+    find_members_in(root, &mut members)?;
+
+    if members.is_empty() {
+        bail!("No packages were found in {}", root.display());
+    }
+
+    Ok(members)
+}
+
+fn find_members_in(dir: &Path, members: &mut Vec<WorkspaceMember>) -> anyhow::Result<()> {
+    let mut skipped = BTreeSet::new();
+
+    if package_compat::handle_based_on_file_path(dir)?.is_some() {
+        let member = WorkspaceMember::load(dir)?;
+        skipped.extend(
+            member
+                .manifest
+                .install
+                .package_dirs()
+                .iter()
+                .map(|package_dir| dir.join(package_dir)),
+        );
+        members.push(member);
+    }
+
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with('.'));
+
+        if entry.is_dir() && !hidden && !skipped.contains(&entry) {
+            find_members_in(&entry, members)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sort workspace members so that every package comes after the members it
+/// depends on. Packages that don't depend on each other keep their relative
+/// order. Dev dependencies don't count, since they never have to be in the
+/// index for a package to be published.
+pub fn publish_order(members: Vec<WorkspaceMember>) -> anyhow::Result<Vec<WorkspaceMember>> {
+    let mut seen = BTreeMap::new();
+    for (index, member) in members.iter().enumerate() {
+        if let Some(other) = seen.insert(&member.manifest.package.name, index) {
+            bail!(
+                "{} is defined by both {} and {}",
+                member.manifest.package.name,
+                members[other].path.display(),
+                member.path.display()
+            );
+        }
+    }
+
+    // For each member, the members that have to be published before it.
+    let dependencies: Vec<BTreeSet<usize>> = members
+        .iter()
+        .map(|member| {
+            let manifest = &member.manifest;
+
+            manifest
+                .dependencies
+                .values()
+                .chain(manifest.server_dependencies.values())
+                .chain(manifest.client_dependencies.values())
+                .filter_map(|req| {
+                    let index = *seen.get(req.name())?;
+                    let dependency = &members[index].manifest.package;
+
+                    if req.matches(&dependency.name, &dependency.version) {
+                        Some(index)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut order = Vec::with_capacity(members.len());
+    let mut placed = vec![false; members.len()];
+
+    while order.len() < members.len() {
+        let next = (0..members.len())
+            .find(|&index| !placed[index] && dependencies[index].iter().all(|&dep| placed[dep]));
+
+        match next {
+            Some(index) => {
+                placed[index] = true;
+                order.push(index);
+            }
+            None => {
+                let cycle: Vec<String> = (0..members.len())
+                    .filter(|&index| !placed[index])
+                    .map(|index| members[index].manifest.package.name.to_string())
+                    .collect();
+
+                bail!(
+                    "Workspace packages depend on each other in a cycle: {}",
+                    cycle.join(", ")
+                );
+            }
+        }
+    }
+
+    let mut members: Vec<Option<WorkspaceMember>> = members.into_iter().map(Some).collect();
+
+    Ok(order
+        .into_iter()
+        .map(|index| members[index].take().unwrap())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn member(name: &str, version: &str, dependencies: &[&str]) -> WorkspaceMember {
+        let mut source = format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\nrealm = \"shared\"\nregistry = \"test-registries/primary-registry\"\n\n[dependencies]\n",
+            name, version
+        );
+
+        for (index, dependency) in dependencies.iter().enumerate() {
+            source.push_str(&format!("Dep{} = \"{}\"\n", index, dependency));
+        }
+
+        WorkspaceMember {
+            path: PathBuf::from(name),
+            manifest: Manifest::from_slice(source.as_bytes()).unwrap(),
+        }
+    }
+
+    fn names(members: &[WorkspaceMember]) -> Vec<String> {
+        members
+            .iter()
+            .map(|member| member.manifest.package.name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let order = publish_order(vec![
+            member("biff/app", "0.1.0", &["biff/lib@0.1.0", "biff/util@0.1.0"]),
+            member("biff/lib", "0.1.0", &["biff/util@0.1.0"]),
+            member("biff/util", "0.1.0", &[]),
+            member("biff/other", "0.1.0", &[]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            names(&order),
+            vec!["biff/util", "biff/lib", "biff/app", "biff/other"]
+        );
+    }
+
+    #[test]
+    fn unmatched_versions_are_not_ordered() {
+        // The app depends on an older, already published version of the lib.
+        let order = publish_order(vec![
+            member("biff/app", "0.1.0", &["biff/lib@0.1.0"]),
+            member("biff/lib", "0.2.0", &[]),
+        ])
+        .unwrap();
+
+        assert_eq!(names(&order), vec!["biff/app", "biff/lib"]);
+    }
+
+    #[test]
+    fn cycles_fail() {
+        let err = publish_order(vec![
+            member("biff/a", "0.1.0", &["biff/b@0.1.0"]),
+            member("biff/b", "0.1.0", &["biff/a@0.1.0"]),
+            member("biff/c", "0.1.0", &[]),
+        ])
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("biff/a, biff/b"), "{}", err);
+    }
+
+    #[test]
+    fn dev_dependency_cycles_publish() {
+        // The lib's tests use the app, which is common in a monorepo.
+        let mut lib = member("biff/lib", "0.1.0", &[]);
+        lib.manifest
+            .dev_dependencies
+            .insert("App".to_owned(), "biff/app@0.1.0".parse().unwrap());
+
+        let order =
+            publish_order(vec![member("biff/app", "0.1.0", &["biff/lib@0.1.0"]), lib]).unwrap();
+
+        assert_eq!(names(&order), vec!["biff/lib", "biff/app"]);
+    }
+
+    #[test]
+    fn duplicate_names_fail() {
+        let err = publish_order(vec![
+            member("biff/a", "0.1.0", &[]),
+            member("biff/a", "0.2.0", &[]),
+        ])
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("defined by both"), "{}", err);
+    }
+}
@@ -0,0 +1,3 @@
+return function()
+	print("howdy")
+end
@@ -0,0 +1,5 @@
+local Minimal = require(script.Vendor.Minimal)
+
+return function()
+	print(Minimal)
+end
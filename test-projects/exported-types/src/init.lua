@@ -0,0 +1,6 @@
+-- export type Hidden = number
+export type Point = { x: number, y: number }
+export type Callback<T... = ...any> = (T...) -> ()
+export type Map<K, V = string> = { [K]: V }
+
+return {}
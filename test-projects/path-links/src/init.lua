@@ -0,0 +1,5 @@
+local Minimal = require("@Packages/Minimal")
+
+return function()
+	print(Minimal)
+end
@@ -0,0 +1,5 @@
+local Minimal = require(script.Parent.Parent.Packages.Minimal)
+
+return function()
+	print(Minimal)
+end
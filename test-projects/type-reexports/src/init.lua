@@ -0,0 +1,5 @@
+local Types = require(script.Parent.Types)
+
+export type Point = Types.Point
+
+return Types
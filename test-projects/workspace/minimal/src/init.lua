@@ -0,0 +1 @@
+return "hey"
\ No newline at end of file
@@ -1,6 +1,16 @@
 use super::temp_project::TempProject;
-use libgooey::{Args, GlobalOptions, InstallSubcommand, Subcommand};
+use libgooey::{
+    manifest::Manifest,
+    package_contents::PackageContents,
+    package_index::Deprecation,
+    package_name::PackageName,
+    package_source::{PackageSource, PackageSourceMap, TestRegistry},
+    resolution::resolve,
+    Args, GlobalOptions, InstallSubcommand, Subcommand,
+};
+use std::collections::BTreeSet;
 use std::path::Path;
+use tempfile::tempdir;
 
 #[test]
 fn minimal() {
@@ -42,7 +52,73 @@ fn cross_realm_explicit_dependency() {
     run_test("cross-realm-explicit-dependency");
 }
 
+#[test]
+fn client_dependency() {
+    run_test("client-dependency");
+}
+
+#[test]
+fn type_reexports() {
+    run_test("type-reexports");
+}
+
+#[test]
+fn path_links() {
+    run_test("path-links");
+}
+
+#[test]
+fn custom_folders() {
+    let project = run_test("custom-folders");
+
+    // Installed packages should never end up in a published package, even
+    // when they live inside the project's source.
+    let contents = PackageContents::pack_from_path(project.path()).unwrap();
+    let unpacked = tempdir().unwrap();
+    contents.unpack_into_path(unpacked.path()).unwrap();
+
+    assert!(unpacked.path().join("src/init.lua").exists());
+    assert!(!unpacked.path().join("src/Vendor").exists());
+    assert!(!unpacked.path().join("ServerVendor").exists());
+}
+
+#[test]
+fn sourcemap() {
+    run_test_with_sourcemap("sourcemap", true);
+}
+
+#[test]
+fn deprecated_dependency() {
+    let project = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test-projects/type-reexports"
+    ));
+    let manifest = Manifest::load(project).unwrap();
+    let package_sources = PackageSourceMap::new(Box::new(PackageSource::TestRegistry(
+        TestRegistry::new(&manifest.package.registry),
+    )));
+
+    let resolved = resolve(&manifest, &BTreeSet::new(), &package_sources).unwrap();
+    let deprecations = resolved.deprecations(&package_sources);
+
+    assert_eq!(deprecations.len(), 1);
+
+    let (package_id, deprecation) = &deprecations[0];
+    assert_eq!(package_id.name().to_string(), "biff/exported-types");
+    assert_eq!(
+        deprecation,
+        &Deprecation {
+            message: "No longer maintained".to_owned(),
+            replacement: Some(PackageName::new("biff", "minimal").unwrap()),
+        }
+    );
+}
+
 fn run_test(name: &str) -> TempProject {
+    run_test_with_sourcemap(name, false)
+}
+
+fn run_test_with_sourcemap(name: &str, sourcemap: bool) -> TempProject {
     let source_project =
         Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test-projects",)).join(name);
 
@@ -55,6 +131,8 @@ fn run_test(name: &str) -> TempProject {
         },
         subcommand: Subcommand::Install(InstallSubcommand {
             project_path: project.path().to_owned(),
+            download: Default::default(),
+            sourcemap,
         }),
     };
 
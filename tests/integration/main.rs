@@ -3,6 +3,7 @@ mod util;
 mod temp_project;
 
 mod install;
+mod package;
 mod publish;
 mod read_projects;
 mod update;
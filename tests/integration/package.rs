@@ -0,0 +1,110 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use fs_err as fs;
+use libgooey::package_contents::PackageContents;
+use tempfile::tempdir;
+use zip::{DateTime, ZipArchive};
+
+use super::temp_project::TempProject;
+
+fn write(root: &Path, path: &str, contents: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// Ignore files should be read relative to the project being packed, with
+/// the same semantics git gives them.
+#[test]
+fn ignore_files() {
+    let project = tempdir().unwrap();
+    let root = project.path();
+
+    write(
+        root,
+        "gooey.toml",
+        indoc::indoc! {r#"
+            [package]
+            name = "biff/ignore-files"
+            version = "0.1.0"
+            license = "MIT"
+            realm = "shared"
+            registry = "test-registries/primary-registry"
+        "#},
+    );
+    write(
+        root,
+        ".gitignore",
+        "# build output\n/build\n*.log\n!keep.log\n",
+    );
+    write(root, ".gooeyignore", "notes.md\n");
+    write(root, "src/init.lua", "return nil");
+    write(root, "src/debug.log", "");
+    write(root, "src/keep.log", "");
+    write(root, "src/build/inner.lua", "return nil");
+    write(root, "src/generated/.gitignore", "*.lua\n");
+    write(root, "src/generated/a.lua", "return nil");
+    write(root, "src/generated/README.md", "");
+    write(root, "build/out.lua", "return nil");
+    write(root, "notes.md", "");
+    write(root, "# build output", "");
+
+    let contents = PackageContents::filtered_contents(root).unwrap();
+    let relative: Vec<String> = contents
+        .iter()
+        .map(|path| {
+            let relative = path.strip_prefix(root).unwrap();
+            relative.to_str().unwrap().replace('\\', "/")
+        })
+        .collect();
+
+    assert_eq!(
+        relative,
+        vec![
+            "# build output",
+            "gooey.toml",
+            "src",
+            "src/build",
+            "src/build/inner.lua",
+            "src/generated",
+            "src/generated/README.md",
+            "src/init.lua",
+            "src/keep.log",
+        ]
+    );
+}
+
+/// Packing the same files should produce the same bytes, no matter when or
+/// where the files were written.
+#[test]
+fn reproducible_archives() {
+    let source = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test-projects/minimal"
+    ));
+    let copy = TempProject::new(source).unwrap();
+
+    let first = PackageContents::pack_from_path(source).unwrap();
+    let second = PackageContents::pack_from_path(copy.path()).unwrap();
+
+    assert!(first.data() == second.data(), "archives were not identical");
+
+    let mut archive = ZipArchive::new(Cursor::new(first.data())).unwrap();
+    let mut names = Vec::new();
+
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).unwrap();
+        let modified = file.last_modified();
+        let expected = DateTime::default();
+        assert_eq!(
+            (modified.datepart(), modified.timepart()),
+            (expected.datepart(), expected.timepart())
+        );
+        names.push(file.name().to_owned());
+    }
+
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+}
@@ -29,6 +29,9 @@ fn check_prompts_auth() {
         subcommand: Subcommand::Publish(PublishSubcommand {
             project_path: test_projects.join("minimal"),
             token: None,
+            dry_run: false,
+            workspace: false,
+            projects: Vec::new(),
         }),
     };
 
@@ -79,6 +82,9 @@ fn check_private_field() {
         subcommand: Subcommand::Publish(PublishSubcommand {
             project_path: test_projects.join("private-package"),
             token: None,
+            dry_run: false,
+            workspace: false,
+            projects: Vec::new(),
         }),
     };
 
@@ -113,9 +119,156 @@ fn check_token_arg() {
         subcommand: Subcommand::Publish(PublishSubcommand {
             project_path: test_projects.join("minimal"),
             token: Some("token".to_owned()),
+            dry_run: false,
+            workspace: false,
+            projects: Vec::new(),
         }),
     };
 
     args.run()
         .expect("Publish did not use the provided token in the publish request");
 }
+
+/// A dry run should pack and validate the package without needing to log in
+/// or upload anything.
+#[test]
+#[serial]
+fn check_dry_run() {
+    let test_projects = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test-projects"));
+    let test_registry = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test-registries/primary-registry"
+    ));
+
+    git_util::init_test_repo(&test_registry.join("index")).unwrap();
+
+    let args = Args {
+        global: GlobalOptions {
+            test_registry: true,
+            use_temp_index: true,
+            ..Default::default()
+        },
+        subcommand: Subcommand::Publish(PublishSubcommand {
+            project_path: test_projects.join("mismatched-name"),
+            token: None,
+            dry_run: true,
+            workspace: false,
+            projects: Vec::new(),
+        }),
+    };
+
+    args.run().expect("Dry run should succeed without a token");
+}
+
+/// A dry run should fail if the version has already been published.
+#[test]
+#[serial]
+fn check_dry_run_existing_version() {
+    let test_projects = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test-projects"));
+    let test_registry = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test-registries/primary-registry"
+    ));
+
+    git_util::init_test_repo(&test_registry.join("index")).unwrap();
+
+    let args = Args {
+        global: GlobalOptions {
+            test_registry: true,
+            use_temp_index: true,
+            ..Default::default()
+        },
+        subcommand: Subcommand::Publish(PublishSubcommand {
+            project_path: test_projects.join("minimal"),
+            token: None,
+            dry_run: true,
+            workspace: false,
+            projects: Vec::new(),
+        }),
+    };
+
+    let error = args.run().expect_err("Expected dry run to return an error");
+
+    assert!(
+        error.to_string().contains("already exists"),
+        "Expected error message that the version exists. Instead we got: {:#}",
+        error
+    )
+}
+
+/// Publishing a workspace should find every package in it, skipping versions
+/// that are already published and packages installed as dependencies.
+#[test]
+#[serial]
+fn check_workspace_dry_run() {
+    let test_projects = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test-projects"));
+    let test_registry = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test-registries/primary-registry"
+    ));
+
+    git_util::init_test_repo(&test_registry.join("index")).unwrap();
+
+    let args = Args {
+        global: GlobalOptions {
+            test_registry: true,
+            use_temp_index: true,
+            ..Default::default()
+        },
+        subcommand: Subcommand::Publish(PublishSubcommand {
+            project_path: test_projects.join("workspace"),
+            token: None,
+            dry_run: true,
+            workspace: true,
+            projects: Vec::new(),
+        }),
+    };
+
+    args.run().expect("Workspace dry run should succeed");
+}
+
+/// Publishing several projects should stop at the first one that fails.
+#[test]
+#[serial]
+fn check_projects_stop_at_failure() {
+    let test_projects = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test-projects"));
+    let test_registry = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test-registries/primary-registry"
+    ));
+
+    git_util::init_test_repo(&test_registry.join("index")).unwrap();
+
+    let args = Args {
+        global: GlobalOptions {
+            test_registry: true,
+            use_temp_index: true,
+            ..Default::default()
+        },
+        subcommand: Subcommand::Publish(PublishSubcommand {
+            project_path: test_projects.to_owned(),
+            token: None,
+            dry_run: false,
+            workspace: false,
+            projects: vec![
+                test_projects.join("workspace/app"),
+                test_projects.join("workspace/lib"),
+            ],
+        }),
+    };
+
+    let error = args.run().expect_err("Expected publish to return an error");
+
+    assert!(
+        error
+            .to_string()
+            .contains("Failed to publish biff/workspace-lib@0.1.0"),
+        "Expected the dependency to be published first. Instead we got: {:#}",
+        error
+    );
+    assert!(
+        format!("{:#}", error).contains("gooey login"),
+        "Expected error message prompting user to login. Instead we got: {:#}",
+        error
+    );
+}